@@ -0,0 +1,4 @@
+-- NULL until the user verifies their email. The temporary default marks the accounts
+-- existing before this migration as verified.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS email_verified_at TIMESTAMPTZ DEFAULT now();
+ALTER TABLE users ALTER COLUMN email_verified_at DROP DEFAULT;
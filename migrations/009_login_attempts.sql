@@ -0,0 +1,10 @@
+-- Failed login counters, keyed by the lowercased email or by the client IP
+CREATE TABLE IF NOT EXISTS login_attempts (
+    kind VARCHAR(16) NOT NULL CHECK (kind IN ('email', 'ip')),
+    key VARCHAR(255) NOT NULL,
+    failures INTEGER NOT NULL,
+    last_failure_at TIMESTAMPTZ NOT NULL,
+    -- Logins of the key are refused until then
+    locked_until TIMESTAMPTZ,
+    PRIMARY KEY (kind, key)
+);
@@ -0,0 +1,9 @@
+-- GCRA state of the rate limits when RATE_LIMIT_STORE=postgres, shared by every replica.
+-- tat is the theoretical arrival time in seconds since the epoch, the client has its full
+-- quota again once it is in the past.
+CREATE UNLOGGED TABLE IF NOT EXISTS rate_limits (
+    bucket VARCHAR(255) NOT NULL,
+    key VARCHAR(255) NOT NULL,
+    tat DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (bucket, key)
+);
@@ -1,3 +1,8 @@
 pub mod handler;
+pub mod mfa;
 mod model;
-mod repository;
\ No newline at end of file
+mod repository;
+pub mod session;
+pub mod throttle;
+pub mod token_sweeper;
+pub mod verification;
\ No newline at end of file
@@ -1 +1,12 @@
-pub mod login_user;
\ No newline at end of file
+pub mod confirm_mfa;
+pub mod enroll_mfa;
+pub mod forgot_password;
+pub mod login_user;
+pub mod logout_user;
+pub mod refresh_token;
+pub mod register_user;
+pub mod resend_verification;
+pub mod reset_password;
+pub mod verify_email;
+pub mod verify_mfa;
+mod util;
\ No newline at end of file
@@ -0,0 +1,83 @@
+use crate::libs::request::Request;
+use crate::AppState;
+use super::super::mfa::{generate_recovery_codes, hash_recovery_code};
+use super::super::model::MfaConfirmInput;
+use super::super::repository::{enable_mfa, get_mfa, replace_recovery_codes};
+use crate::libs::error::{AppError, ErrorDetail};
+use crate::libs::response::{Response, StatusCode};
+use crate::libs::totp;
+use log::{error, info};
+
+// Enables two-factor authentication once the user proves the authenticator app works,
+// and returns the recovery codes. They are shown this one time only.
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let auth = match &request.auth {
+        Some(auth) => auth,
+        None => return AppError::Unauthorized("Unauthorized".to_string()).into(),
+    };
+    let input: MfaConfirmInput = match serde_json::from_slice(&request.body) {
+        Ok(input) => input,
+        Err(e) => return AppError::BadRequest(format!("Failed to parse request body: {}", e)).into(),
+    };
+
+    let mut db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    let mfa = match get_mfa(&auth.user_id, &**db).await {
+        Ok(Some(mfa)) if !mfa.enabled => mfa,
+        Ok(Some(_)) => return AppError::BadRequest("Two-factor authentication is already enabled".to_string()).into(),
+        Ok(None) => return AppError::BadRequest("Start the enrolment with POST /auth/mfa/enroll first".to_string()).into(),
+        Err(e) => {
+            error!("Error getting mfa of user {}: {:?}", auth.user_id, e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+    let step = match totp::verify(&mfa.secret, &input.code, chrono::Utc::now().timestamp()) {
+        Some(step) => step,
+        None => {
+            return AppError::Validation(vec![ErrorDetail {
+                field: Some("code".to_string()),
+                message: "Invalid authentication code".to_string(),
+            }]).into();
+        }
+    };
+
+    let recovery_codes = generate_recovery_codes();
+    let code_hashes: Vec<String> = recovery_codes.iter().map(|code| hash_recovery_code(code)).collect();
+    let tx = match db.transaction().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {:?}", e);
+            return AppError::Internal("Failed to start transaction".to_string()).into();
+        }
+    };
+    match enable_mfa(&auth.user_id, step, &*tx).await {
+        // Confirmed by a concurrent request
+        Ok(0) => return AppError::BadRequest("Two-factor authentication is already enabled".to_string()).into(),
+        Ok(_) => {}
+        Err(e) => {
+            error!("Error enabling mfa of user {}: {:?}", auth.user_id, e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    }
+    if let Err(e) = replace_recovery_codes(&auth.user_id, &code_hashes, &*tx).await {
+        error!("Error storing recovery codes of user {}: {:?}", auth.user_id, e);
+        return AppError::Internal("Internal error".to_string()).into();
+    }
+    if let Err(e) = tx.commit().await {
+        error!("Failed to commit transaction: {:?}", e);
+        return AppError::Internal("Failed to commit transaction".to_string()).into();
+    }
+
+    info!("User {} enabled two-factor authentication", auth.user_id);
+    let body = serde_json::json!({
+        "recovery_codes": recovery_codes
+    }).to_string();
+
+    Response::json(StatusCode::Ok, body)
+}
\ No newline at end of file
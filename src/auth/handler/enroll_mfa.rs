@@ -0,0 +1,40 @@
+use crate::libs::request::Request;
+use crate::AppState;
+use super::super::repository::upsert_mfa_secret;
+use crate::libs::error::AppError;
+use crate::libs::response::{Response, StatusCode};
+use crate::libs::totp;
+use log::{error, info};
+
+// Starts an enrolment with a new secret. It has no effect on login until POST /auth/mfa/confirm.
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let auth = match &request.auth {
+        Some(auth) => auth,
+        None => return AppError::Unauthorized("Unauthorized".to_string()).into(),
+    };
+
+    let db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    let secret = totp::generate_secret();
+    match upsert_mfa_secret(&auth.user_id, &secret, &**db).await {
+        Ok(0) => return AppError::BadRequest("Two-factor authentication is already enabled".to_string()).into(),
+        Ok(_) => info!("User {} started two-factor enrolment", auth.user_id),
+        Err(e) => {
+            error!("Error storing mfa secret: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    }
+
+    let body = serde_json::json!({
+        "secret": secret,
+        "otpauth_uri": totp::provisioning_uri(&secret, &state.config.mfa.issuer, &auth.email)
+    }).to_string();
+
+    Response::json(StatusCode::Ok, body)
+}
\ No newline at end of file
@@ -0,0 +1,78 @@
+use std::sync::Arc;
+use deadpool_postgres::Pool;
+use crate::libs::request::Request;
+use crate::AppState;
+use super::super::model::ForgotPasswordInput;
+use super::super::repository::insert_password_reset_token;
+use crate::libs::error::AppError;
+use crate::libs::mailer::{Email, Mailer};
+use crate::libs::response::{Response, StatusCode};
+use crate::libs::token::{generate_opaque_token, hash_opaque_token};
+use crate::users::model::User;
+use crate::users::repository::get_user_by_email;
+use log::{error, info};
+
+// Always answers 204, so the endpoint cannot be used to find out which emails have accounts
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let input: ForgotPasswordInput = match serde_json::from_slice(&request.body) {
+        Ok(input) => input,
+        Err(e) => return AppError::BadRequest(format!("Failed to parse request body: {}", e)).into(),
+    };
+
+    let db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    let user = match get_user_by_email(&input.email, &db).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Response::new(StatusCode::NoContent),
+        Err(e) => {
+            error!("Error getting user by email: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    // The token is stored and mailed from a background task, so a known email answers as
+    // fast as an unknown one
+    let url = match &state.config.password_reset.url {
+        Some(url) => url.clone(),
+        None => format!("{}/reset-password", state.config.mail.base_url.trim_end_matches('/')),
+    };
+    tokio::spawn(send_reset_email(state.db_pool.clone(), state.mailer.clone(), user, state.config.password_reset.ttl, url));
+
+    Response::new(StatusCode::NoContent)
+}
+
+async fn send_reset_email(pool: Pool, mailer: Arc<dyn Mailer>, user: User, ttl: i64, url: String) {
+    let db = match pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return;
+        }
+    };
+
+    // Only the hash is stored, the token itself exists in the email alone
+    let token = generate_opaque_token();
+    if let Err(e) = insert_password_reset_token(&user.id, &hash_opaque_token(&token), ttl, &**db).await {
+        error!("Error storing password reset token: {:?}", e);
+        return;
+    }
+
+    let email = Email {
+        to: user.email.clone(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hi {},\n\nOpen the link below to choose a new password. It can be used once and expires in {} minutes.\n\n{}?token={}\n\nIf you did not ask for this, you can ignore this email.\n",
+            user.name, ttl / 60, url, token
+        ),
+    };
+    match mailer.send(&email).await {
+        Ok(()) => info!("Sent password reset email to user {}", user.id),
+        Err(e) => error!("Error sending password reset email to {}: {:?}", email.to, e),
+    }
+}
@@ -1,64 +1,168 @@
+use std::sync::OnceLock;
 use tokio_postgres::Client;
+use crate::libs::request::Request;
+use crate::AppState;
 use super::super::model::LoginUserInput;
-use crate::libs::{BAD_REQUEST, OK_RESPONSE, INTERNAL_ERROR};
-use crate::users::repository::get_password_by_email;
+use crate::libs::error::AppError;
+use crate::libs::response::Response;
+use crate::users::model::Credentials;
+use crate::libs::token::generate_opaque_token;
+use crate::users::repository::get_credentials_by_email;
+use super::super::mfa::challenge_response;
+use super::super::throttle;
+use super::super::repository::get_mfa;
+use super::util::{issue_refresh_token, token_response};
 use log::error;
 use bcrypt;
-use crate::libs::token::claim_jwt_token;
 
-pub async fn handle(request: &str, db: &Client) -> (String, String) {
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let mut db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
     let login_input: LoginUserInput= match get_user_login_input(request) {
         Ok(login_input) => login_input,
-        Err(msg) => return (BAD_REQUEST.to_string(), msg.to_string()),
+        Err(msg) => return AppError::BadRequest(msg).into(),
     };
 
-    match validate(&login_input, db).await {
-        Ok(()) => (),
-        Err(e) => return (BAD_REQUEST.to_string(), e.to_string()),
+    if login_input.email.is_empty() || login_input.password.is_empty() {
+        return AppError::BadRequest("Invalid email or password".to_string()).into();
+    }
+
+    let ip = request.remote_addr.map(|addr| addr.ip());
+    let throttle_config = &state.config.login_throttle;
+    if let Err(e) = throttle::begin(throttle_config, &login_input.email, ip, &mut db).await {
+        error!("Refused login of {}: {}", login_input.email, e);
+        return e.into();
+    }
+
+    let credentials = match validate(&login_input, &db).await {
+        Ok(credentials) => credentials,
+        Err(e) => return e.into(),
     };
+    if let Err(e) = throttle::release_ip(throttle_config, ip, &**db).await {
+        return e.into();
+    }
+    // Checked after the password so it does not reveal whether an account exists
+    if state.config.verification.required_for_login && !credentials.email_verified {
+        return AppError::EmailNotVerified.into();
+    }
+    let user_id = credentials.id;
 
-    let token = match claim_jwt_token(login_input.email) {
-        Ok(token) => token,
+    // The attempt of the email stays counted until the second factor is proven as well
+    match get_mfa(&user_id, &**db).await {
+        Ok(Some(mfa)) if mfa.enabled => return challenge_response(state, user_id, login_input.email),
+        Ok(_) => {}
         Err(e) => {
-            return (INTERNAL_ERROR.to_string(), e.to_string());
+            error!("Error getting mfa of user {}: {:?}", user_id, e);
+            return AppError::Internal("Internal error".to_string()).into();
         }
-    };
+    }
 
-    let body = serde_json::json!({
-        "token": token
-    }).to_string();
+    // Every login starts a new refresh token family
+    let family_id = generate_opaque_token();
+    let refresh_token = match issue_refresh_token(&user_id, &family_id, state.config.token.refresh_ttl, &**db).await {
+        Ok(refresh_token) => refresh_token,
+        Err(e) => return e.into(),
+    };
+    if let Err(e) = throttle::clear(&login_input.email, &**db).await {
+        return e.into();
+    }
 
-    (OK_RESPONSE.to_string(), String::from(body))
+    token_response(&state.keyring, user_id, login_input.email, credentials.role, refresh_token)
 }
 
-fn get_user_login_input(request: &str) -> Result<LoginUserInput, String> {
-    let body = request.split("\r\n\r\n").last().unwrap_or_default();
-    serde_json::from_str(body).map_err(|e| {
+fn get_user_login_input(request: &Request) -> Result<LoginUserInput, String> {
+    serde_json::from_slice(&request.body).map_err(|e| {
         format!("Failed to parse request body: {}", e)
     })
 }
 
-async fn validate(user: &LoginUserInput, db: &Client) -> Result<(), Box<dyn std::error::Error>> {
-    if user.email.is_empty() || user.password.is_empty() {
-        return Err("Invalid email or password".into())
-    }
-
-    let password = match get_password_by_email(&user.email, db).await {
-        Ok(password) => password.trim_end().to_string(),
+// Returns the stored credentials of the user when the password matches
+async fn validate(user: &LoginUserInput, db: &Client) -> Result<Credentials, AppError> {
+    let (found, hash) = match get_credentials_by_email(&user.email, db).await {
+        Ok(Some(credentials)) => {
+            let hash = credentials.password.trim_end().to_string();
+            (Some(credentials), Some(hash))
+        }
+        Ok(None) => (None, None),
         Err(e) => {
             error!("Error getting password: {:?}", e);
-            return Err("Invalid email or password".into())
+            return Err(AppError::Internal("Internal error".to_string()))
         }
     };
 
-    match bcrypt::verify(&user.password, &password) {
-        Ok(_) => (),
+    // Verified before matching, a guard on Some(found) would skip the dummy hash
+    let valid = verify_password(&user.password, hash.as_deref());
+    match found {
+        Some(found) if valid => Ok(found),
+        _ => Err(AppError::Unauthorized("Invalid email or password".to_string())),
+    }
+}
+
+// Unknown emails are checked against a dummy hash of the same cost, so the response time
+// does not tell whether an account exists
+fn verify_password(password: &str, hash: Option<&str>) -> bool {
+    let (hash, known) = match hash {
+        Some(hash) => (hash, true),
+        None => (dummy_hash(), false),
+    };
+
+    match bcrypt::verify(password, hash) {
+        Ok(valid) => valid && known,
         Err(e) => {
             error!("Error verifying password: {:?}", e);
-            return Err("Invalid email or password".into())
+            false
         }
     }
+}
+
+// Called from main before the server accepts connections, so the first login with an
+// unknown email does not pay for hashing it
+pub fn dummy_hash() -> &'static str {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    DUMMY_HASH.get_or_init(|| {
+        bcrypt::hash("dummy password", bcrypt::DEFAULT_COST).expect("Failed to hash dummy password")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &str = "Secret123!x";
+
+    fn hash(password: &str) -> String {
+        bcrypt::hash(password, 4).unwrap()
+    }
 
-    Ok(())
+    #[test]
+    fn rejects_wrong_password() {
+        let hash = hash(PASSWORD);
+        assert!(!verify_password("Wrong123!x", Some(&hash)));
+    }
 
+    #[test]
+    fn rejects_unknown_email() {
+        assert!(!verify_password(PASSWORD, None));
+    }
+
+    #[test]
+    fn rejects_unknown_email_even_with_dummy_password() {
+        assert!(!verify_password("dummy password", None));
+    }
+
+    #[test]
+    fn rejects_invalid_stored_hash() {
+        assert!(!verify_password(PASSWORD, Some("not a bcrypt hash")));
+    }
+
+    #[test]
+    fn accepts_correct_password() {
+        let hash = hash(PASSWORD);
+        assert!(verify_password(PASSWORD, Some(&hash)));
+    }
 }
\ No newline at end of file
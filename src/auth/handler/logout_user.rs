@@ -0,0 +1,52 @@
+use crate::libs::request::Request;
+use crate::AppState;
+use super::super::model::LogoutInput;
+use super::super::repository::{insert_revoked_token, revoke_refresh_token_family_of};
+use crate::libs::error::AppError;
+use crate::libs::response::{Response, StatusCode};
+use crate::libs::token::hash_opaque_token;
+use log::error;
+
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let auth = match &request.auth {
+        Some(auth) => auth,
+        None => return AppError::Unauthorized("Unauthorized".to_string()).into(),
+    };
+    let input = match get_logout_input(request) {
+        Ok(input) => input,
+        Err(msg) => return AppError::BadRequest(msg).into(),
+    };
+
+    let db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    if let Err(e) = insert_revoked_token(&auth.jti, auth.exp, &**db).await {
+        error!("Error revoking token: {:?}", e);
+        return AppError::Internal("Internal error".to_string()).into();
+    }
+    state.revoked_tokens.revoke(auth.jti.clone(), auth.exp);
+
+    if let Some(refresh_token) = input.refresh_token {
+        if let Err(e) = revoke_refresh_token_family_of(&hash_opaque_token(&refresh_token), &auth.email, &**db).await {
+            error!("Error revoking refresh token family: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    }
+
+    Response::new(StatusCode::NoContent)
+}
+
+// The body is optional, an empty one logs out the access token only
+fn get_logout_input(request: &Request) -> Result<LogoutInput, String> {
+    if request.body.iter().all(|b| b.is_ascii_whitespace()) {
+        return Ok(LogoutInput::default());
+    }
+    serde_json::from_slice(&request.body).map_err(|e| {
+        format!("Failed to parse request body: {}", e)
+    })
+}
\ No newline at end of file
@@ -0,0 +1,84 @@
+use crate::libs::request::Request;
+use crate::AppState;
+use super::super::model::RefreshTokenInput;
+use super::super::repository::{get_refresh_token_for_update, mark_refresh_token_rotated, revoke_refresh_token_family};
+use super::util::{issue_refresh_token, token_response};
+use crate::libs::error::AppError;
+use crate::libs::response::Response;
+use crate::libs::token::hash_opaque_token;
+use log::{error, warn};
+
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let input: RefreshTokenInput = match get_refresh_token_input(request) {
+        Ok(input) => input,
+        Err(msg) => return AppError::BadRequest(msg).into(),
+    };
+
+    let mut db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+    let tx = match db.transaction().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {:?}", e);
+            return AppError::Internal("Failed to start transaction".to_string()).into();
+        }
+    };
+
+    let token = match get_refresh_token_for_update(&hash_opaque_token(&input.refresh_token), &*tx).await {
+        Ok(Some(token)) => token,
+        Ok(None) => return AppError::Unauthorized("Invalid refresh token".to_string()).into(),
+        Err(e) => {
+            error!("Error getting refresh token: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    if token.revoked {
+        return AppError::Unauthorized("Invalid refresh token".to_string()).into();
+    }
+
+    // A rotated token coming back means it was stolen, either by whoever sends it now or by
+    // whoever refreshed before. The whole family is revoked so both have to log in again.
+    if token.rotated {
+        warn!("Reuse of rotated refresh token {} of user {}, revoking its family", token.id, token.user_id);
+        if let Err(e) = revoke_refresh_token_family(&token.family_id, &*tx).await {
+            error!("Error revoking refresh token family: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+        if let Err(e) = tx.commit().await {
+            error!("Failed to commit transaction: {:?}", e);
+            return AppError::Internal("Failed to commit transaction".to_string()).into();
+        }
+        return AppError::Unauthorized("Invalid refresh token".to_string()).into();
+    }
+
+    if token.expired {
+        return AppError::Unauthorized("Refresh token expired".to_string()).into();
+    }
+
+    if let Err(e) = mark_refresh_token_rotated(&token.id, &*tx).await {
+        error!("Error rotating refresh token: {:?}", e);
+        return AppError::Internal("Internal error".to_string()).into();
+    }
+    let refresh_token = match issue_refresh_token(&token.user_id, &token.family_id, state.config.token.refresh_ttl, &*tx).await {
+        Ok(refresh_token) => refresh_token,
+        Err(e) => return e.into(),
+    };
+    if let Err(e) = tx.commit().await {
+        error!("Failed to commit transaction: {:?}", e);
+        return AppError::Internal("Failed to commit transaction".to_string()).into();
+    }
+
+    token_response(&state.keyring, token.user_id, token.email, token.role, refresh_token)
+}
+
+fn get_refresh_token_input(request: &Request) -> Result<RefreshTokenInput, String> {
+    serde_json::from_slice(&request.body).map_err(|e| {
+        format!("Failed to parse request body: {}", e)
+    })
+}
\ No newline at end of file
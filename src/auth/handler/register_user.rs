@@ -0,0 +1,51 @@
+use crate::libs::request::Request;
+use crate::AppState;
+use super::super::verification::send_verification_email;
+use crate::libs::error::{AppError, ErrorDetail};
+use crate::libs::response::{Response, StatusCode};
+use crate::users::handler::create_user::create;
+use crate::users::handler::util::get_user_create_input;
+use log::{error, info};
+
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let user = match get_user_create_input(request) {
+        Ok(user) => user,
+        Err(msg) => {
+            error!("Error registering user: {}", msg);
+            return AppError::BadRequest(msg).into();
+        }
+    };
+    // Self-registered accounts are always plain users
+    if user.role.is_some() {
+        return AppError::Validation(vec![ErrorDetail {
+            field: Some("role".to_string()),
+            message: "role cannot be chosen when registering".to_string(),
+        }]).into();
+    }
+
+    let mut db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    let email_verified = !state.config.registration.require_verification;
+    let user = match create(&user, email_verified, &mut db, &state.password_policy).await {
+        Ok(user) => user,
+        Err(e) => return e.into(),
+    };
+    info!("User {} registered from {:?}", user.id, request.remote_addr);
+    if !user.email_verified {
+        send_verification_email(state, user.id, &user.name, &user.email);
+    }
+
+    match serde_json::to_string(&user.tranform_to_user_response()) {
+        Ok(user) => Response::json(StatusCode::Ok, user),
+        Err(e) => {
+            error!("Error serializing user: {:?}", e);
+            AppError::Internal("Internal error".to_string()).into()
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,36 @@
+use crate::libs::request::Request;
+use crate::AppState;
+use super::super::model::ResendVerificationInput;
+use super::super::verification::send_verification_email;
+use crate::libs::error::AppError;
+use crate::libs::response::{Response, StatusCode};
+use crate::users::repository::get_user_by_email;
+use log::error;
+
+// Always answers 204, so the endpoint cannot be used to find out which emails have accounts
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let input: ResendVerificationInput = match serde_json::from_slice(&request.body) {
+        Ok(input) => input,
+        Err(e) => return AppError::BadRequest(format!("Failed to parse request body: {}", e)).into(),
+    };
+
+    let db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    match get_user_by_email(&input.email, &db).await {
+        // Sent in the background, so a known email answers as fast as an unknown one
+        Ok(Some(user)) if !user.email_verified => send_verification_email(state, user.id, &user.name, &user.email),
+        Ok(_) => {}
+        Err(e) => {
+            error!("Error getting user by email: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    }
+
+    Response::new(StatusCode::NoContent)
+}
\ No newline at end of file
@@ -0,0 +1,84 @@
+use crate::libs::request::Request;
+use crate::AppState;
+use super::super::model::ResetPasswordInput;
+use super::super::repository::{get_password_reset_for_update, use_password_reset_tokens};
+use super::super::session::revoke_all_sessions;
+use crate::libs::error::AppError;
+use crate::libs::response::{Response, StatusCode};
+use crate::libs::token::hash_opaque_token;
+use crate::libs::validation::{Validate, ValidationErrors};
+use crate::users::handler::create_user::check_password_policy;
+use crate::users::repository::update_password;
+use log::{error, info};
+use bcrypt;
+
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let input: ResetPasswordInput = match serde_json::from_slice(&request.body) {
+        Ok(input) => input,
+        Err(e) => return AppError::BadRequest(format!("Failed to parse request body: {}", e)).into(),
+    };
+    if let Err(e) = input.validate().into_result() {
+        return e.into();
+    }
+
+    let mut db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+    let tx = match db.transaction().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {:?}", e);
+            return AppError::Internal("Failed to start transaction".to_string()).into();
+        }
+    };
+
+    // Locked until commit, so two requests cannot both redeem the same token
+    let reset = match get_password_reset_for_update(&hash_opaque_token(&input.token), &*tx).await {
+        Ok(Some(reset)) => reset,
+        Ok(None) => return AppError::BadRequest("Invalid or expired reset token".to_string()).into(),
+        Err(e) => {
+            error!("Error getting password reset token: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    // The policy needs the name and email of the owner of the token
+    let mut errors = ValidationErrors::new();
+    check_password_policy(&state.password_policy, &input.password, &reset.name, &reset.email, &mut errors);
+    if let Err(e) = errors.into_result() {
+        return e.into();
+    }
+
+    let hash_password = match bcrypt::hash(&input.password, bcrypt::DEFAULT_COST) {
+        Ok(hash_password) => hash_password,
+        Err(e) => {
+            error!("Error hashing password: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+    if let Err(e) = update_password(&reset.user_id, &hash_password, &*tx).await {
+        error!("Error updating password of user {}: {:?}", reset.user_id, e);
+        return AppError::Internal("Internal error".to_string()).into();
+    }
+    // Every other outstanding link stops working as well
+    if let Err(e) = use_password_reset_tokens(&reset.user_id, &*tx).await {
+        error!("Error using password reset tokens of user {}: {:?}", reset.user_id, e);
+        return AppError::Internal("Internal error".to_string()).into();
+    }
+    let revocation = match revoke_all_sessions(reset.user_id, &*tx).await {
+        Ok(revocation) => revocation,
+        Err(e) => return e.into(),
+    };
+    if let Err(e) = tx.commit().await {
+        error!("Failed to commit transaction: {:?}", e);
+        return AppError::Internal("Failed to commit transaction".to_string()).into();
+    }
+    revocation.apply(state);
+
+    info!("User {} reset their password", reset.user_id);
+    Response::new(StatusCode::NoContent)
+}
\ No newline at end of file
@@ -0,0 +1,38 @@
+use tokio_postgres::GenericClient;
+use crate::libs::error::AppError;
+use crate::libs::keyring::Keyring;
+use crate::libs::response::{Response, StatusCode};
+use crate::libs::role::Role;
+use crate::libs::token::{claim_jwt_token, generate_opaque_token, hash_opaque_token};
+use super::super::repository::insert_refresh_token;
+use log::error;
+
+// Stores a new refresh token of the family and returns its plain value for the client
+pub async fn issue_refresh_token(user_id: &i32, family_id: &str, ttl: i64, db: &impl GenericClient) -> Result<String, AppError> {
+    let token = generate_opaque_token();
+    match insert_refresh_token(user_id, family_id, &hash_opaque_token(&token), ttl, db).await {
+        Ok(()) => Ok(token),
+        Err(e) => {
+            error!("Error storing refresh token: {:?}", e);
+            Err(AppError::Internal("Internal error".to_string()))
+        }
+    }
+}
+
+// Response of login and refresh, holding a new access token and refresh token
+pub fn token_response(keyring: &Keyring, user_id: i32, email: String, role: Role, refresh_token: String) -> Response {
+    let token = match claim_jwt_token(user_id, email, vec![role.to_string()], keyring) {
+        Ok(token) => token,
+        Err(e) => {
+            error!("Error claiming jwt token: {}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    let body = serde_json::json!({
+        "token": token,
+        "refresh_token": refresh_token
+    }).to_string();
+
+    Response::json(StatusCode::Ok, body)
+}
\ No newline at end of file
@@ -0,0 +1,70 @@
+use crate::libs::request::Request;
+use crate::AppState;
+use super::super::verification::VERIFY_EMAIL_PURPOSE;
+use crate::libs::error::AppError;
+use crate::libs::response::{Response, StatusCode};
+use crate::libs::token::validate_action_token;
+use crate::users::repository::{get_user_by_id, mark_email_verified};
+use log::{error, info};
+
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let token = match request.query.get("token") {
+        Some(token) if !token.is_empty() => token,
+        _ => return AppError::BadRequest("Missing token".to_string()).into(),
+    };
+    let claims = match validate_action_token(token, VERIFY_EMAIL_PURPOSE, &state.keyring) {
+        Ok(claims) => claims,
+        Err(e) => {
+            error!("Invalid verification token: {}", e);
+            return AppError::BadRequest("Invalid or expired verification link".to_string()).into();
+        }
+    };
+    let id: i32 = match claims.sub.parse() {
+        Ok(id) => id,
+        Err(_) => return AppError::BadRequest("Invalid or expired verification link".to_string()).into(),
+    };
+
+    let db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    let user = match get_user_by_id(&id, &db).await {
+        Ok(user) => user,
+        Err(e) => {
+            error!("Error getting user with id '{}': {}", id, e);
+            return AppError::BadRequest("Invalid or expired verification link".to_string()).into();
+        }
+    };
+    if user.email != claims.email {
+        return AppError::BadRequest("The verification link was sent to a previous email address".to_string()).into();
+    }
+
+    let user = if user.email_verified {
+        user
+    } else {
+        if let Err(e) = mark_email_verified(&id, &claims.email, &db).await {
+            error!("Error verifying email of user '{}': {}", id, e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+        info!("User {} verified {}", id, claims.email);
+        match get_user_by_id(&id, &db).await {
+            Ok(user) => user,
+            _ => {
+                error!("Error getting user with id '{}'", id);
+                return AppError::Internal("Internal error".to_string()).into();
+            }
+        }
+    };
+
+    match serde_json::to_string(&user.tranform_to_user_response()) {
+        Ok(user) => Response::json(StatusCode::Ok, user),
+        Err(e) => {
+            error!("Error serializing user: {:?}", e);
+            AppError::Internal("Internal error".to_string()).into()
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,107 @@
+use crate::libs::request::Request;
+use crate::AppState;
+use super::super::mfa::{hash_recovery_code, MFA_CHALLENGE_PURPOSE};
+use super::super::model::MfaVerifyInput;
+use super::super::repository::{get_mfa, use_recovery_code, use_totp_step};
+use super::super::throttle;
+use crate::libs::error::AppError;
+use crate::libs::response::Response;
+use crate::libs::token::{generate_opaque_token, validate_action_token};
+use crate::libs::totp;
+use crate::libs::validation::Validate;
+use crate::users::repository::get_user_by_id;
+use super::util::{issue_refresh_token, token_response};
+use log::{error, info};
+
+// Second step of a login with two-factor authentication
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let input: MfaVerifyInput = match serde_json::from_slice(&request.body) {
+        Ok(input) => input,
+        Err(e) => return AppError::BadRequest(format!("Failed to parse request body: {}", e)).into(),
+    };
+    if let Err(e) = input.validate().into_result() {
+        return e.into();
+    }
+    let claims = match validate_action_token(&input.mfa_token, MFA_CHALLENGE_PURPOSE, &state.keyring) {
+        Ok(claims) => claims,
+        Err(e) => {
+            error!("Invalid mfa challenge token: {}", e);
+            return AppError::Unauthorized("Invalid or expired mfa_token".to_string()).into();
+        }
+    };
+    let user_id: i32 = match claims.sub.parse() {
+        Ok(user_id) => user_id,
+        Err(_) => return AppError::Unauthorized("Invalid or expired mfa_token".to_string()).into(),
+    };
+
+    let mut db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    // Wrong codes count towards the same limits as wrong passwords
+    let ip = request.remote_addr.map(|addr| addr.ip());
+    let throttle_config = &state.config.login_throttle;
+    if let Err(e) = throttle::begin(throttle_config, &claims.email, ip, &mut db).await {
+        error!("Refused mfa verification of {}: {}", claims.email, e);
+        return e.into();
+    }
+
+    let mfa = match get_mfa(&user_id, &**db).await {
+        Ok(Some(mfa)) if mfa.enabled => mfa,
+        Ok(_) => return AppError::Unauthorized("Invalid or expired mfa_token".to_string()).into(),
+        Err(e) => {
+            error!("Error getting mfa of user {}: {:?}", user_id, e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    let accepted = match (&input.code, &input.recovery_code) {
+        (Some(code), _) => match totp::verify(&mfa.secret, code, chrono::Utc::now().timestamp()) {
+            Some(step) => use_totp_step(&user_id, step, &**db).await,
+            None => Ok(0),
+        },
+        (None, Some(recovery_code)) => use_recovery_code(&user_id, &hash_recovery_code(recovery_code), &**db).await,
+        (None, None) => Ok(0),
+    };
+    match accepted {
+        Ok(0) => return AppError::Unauthorized("Invalid authentication code".to_string()).into(),
+        Ok(_) => {}
+        Err(e) => {
+            error!("Error checking authentication code of user {}: {:?}", user_id, e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    }
+    if let Err(e) = throttle::release_ip(throttle_config, ip, &**db).await {
+        return e.into();
+    }
+    if input.recovery_code.is_some() {
+        info!("User {} logged in with a recovery code", user_id);
+    }
+
+    let user = match get_user_by_id(&user_id, &db).await {
+        Ok(user) => user,
+        Err(e) => {
+            error!("Error getting user with id '{}': {}", user_id, e);
+            return AppError::Unauthorized("Invalid or expired mfa_token".to_string()).into();
+        }
+    };
+    // The challenge was issued for the email the password was checked against
+    if user.email != claims.email {
+        return AppError::Unauthorized("Invalid or expired mfa_token".to_string()).into();
+    }
+
+    let family_id = generate_opaque_token();
+    let refresh_token = match issue_refresh_token(&user_id, &family_id, state.config.token.refresh_ttl, &**db).await {
+        Ok(refresh_token) => refresh_token,
+        Err(e) => return e.into(),
+    };
+    if let Err(e) = throttle::clear(&claims.email, &**db).await {
+        return e.into();
+    }
+
+    token_response(&state.keyring, user_id, user.email, user.role, refresh_token)
+}
\ No newline at end of file
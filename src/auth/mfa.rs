@@ -0,0 +1,48 @@
+use data_encoding::BASE32_NOPAD;
+use rand::RngCore;
+use log::error;
+use crate::AppState;
+use crate::libs::error::AppError;
+use crate::libs::response::{Response, StatusCode};
+use crate::libs::token::{claim_action_token, hash_opaque_token};
+
+pub const MFA_CHALLENGE_PURPOSE: &str = "mfa_challenge";
+const RECOVERY_CODE_COUNT: usize = 10;
+
+// Answer of POST /login for users with two-factor authentication, the access token is only
+// issued by POST /auth/mfa/verify in exchange for the challenge token and a code
+pub fn challenge_response(state: &AppState, user_id: i32, email: String) -> Response {
+    let token = match claim_action_token(MFA_CHALLENGE_PURPOSE, user_id, email, state.config.mfa.challenge_ttl, &state.keyring) {
+        Ok(token) => token,
+        Err(e) => {
+            error!("Error claiming mfa challenge token: {}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    let body = serde_json::json!({
+        "mfa_required": true,
+        "mfa_token": token
+    }).to_string();
+
+    Response::json(StatusCode::Ok, body)
+}
+
+// Codes in the form xxxxx-xxxxx with 50 random bits each
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT).map(|_| {
+        let mut bytes = [0u8; 7];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let code = BASE32_NOPAD.encode(&bytes).to_lowercase();
+        format!("{}-{}", &code[..5], &code[5..10])
+    }).collect()
+}
+
+// Case, dashes and spaces are ignored, so a code can be typed the way it was written down
+pub fn hash_recovery_code(code: &str) -> String {
+    let code: String = code.chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase();
+    hash_opaque_token(&code)
+}
\ No newline at end of file
@@ -1,5 +1,111 @@
+use crate::libs::role::Role;
+use crate::libs::validation::{Validate, ValidationErrors};
+use crate::users::model::validate_new_password;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct LoginUserInput {
     pub email: String,
     pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RefreshTokenInput {
+    pub refresh_token: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct LogoutInput {
+    // Also ends the refresh token family of this session when given
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct RefreshToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub email: String,
+    pub role: Role,
+    pub family_id: String,
+    pub rotated: bool,
+    pub revoked: bool,
+    pub expired: bool,
+}
+
+// User a password reset token was issued to
+#[derive(Debug)]
+pub struct PasswordReset {
+    pub user_id: i32,
+    pub name: String,
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResendVerificationInput {
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ForgotPasswordInput {
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ResetPasswordInput {
+    pub token: String,
+    pub password: String,
+    pub confirm_password: String,
+}
+
+impl Validate for ResetPasswordInput {
+    fn validate(&self) -> ValidationErrors {
+        let mut errors = ValidationErrors::new();
+        if self.token.is_empty() {
+            errors.add("token", "Missing token");
+        }
+        validate_new_password(&self.password, &self.confirm_password, &mut errors);
+        errors
+    }
+}
+
+#[derive(Debug)]
+pub struct Mfa {
+    pub secret: String,
+    pub enabled: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MfaConfirmInput {
+    pub code: String,
+}
+
+// Second login step, answers the challenge with either a TOTP code or a recovery code
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MfaVerifyInput {
+    pub mfa_token: String,
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+impl Validate for MfaVerifyInput {
+    fn validate(&self) -> ValidationErrors {
+        let mut errors = ValidationErrors::new();
+        if self.mfa_token.is_empty() {
+            errors.add("mfa_token", "Missing mfa_token");
+        }
+        match (&self.code, &self.recovery_code) {
+            (Some(_), Some(_)) => errors.add("code", "Send either code or recovery_code, not both"),
+            (None, None) => errors.add("code", "Missing code"),
+            _ => {}
+        }
+        errors
+    }
+}
+
+// Failed login counter of an email or a client IP
+#[derive(Debug)]
+pub struct LoginAttempt {
+    pub kind: String,
+    pub failures: i32,
+    // Unix time until which logins are refused
+    pub locked_until: Option<i64>,
 }
\ No newline at end of file
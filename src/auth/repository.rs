@@ -0,0 +1,256 @@
+use tokio_postgres::{Error, GenericClient};
+use super::model::{LoginAttempt, Mfa, PasswordReset, RefreshToken};
+
+pub async fn insert_refresh_token(user_id: &i32, family_id: &str, token_hash: &str, ttl: i64, db: &impl GenericClient) -> Result<(), Error> {
+    db.execute(
+        "INSERT INTO refresh_tokens (user_id, family_id, token_hash, expires_at) \
+         VALUES ($1, $2, $3, now() + make_interval(secs => $4))",
+        &[user_id, &family_id, &token_hash, &(ttl as f64)],
+    ).await?;
+    Ok(())
+}
+
+// Locks the token row so two concurrent refreshes with the same token cannot both rotate it
+pub async fn get_refresh_token_for_update(token_hash: &str, db: &impl GenericClient) -> Result<Option<RefreshToken>, Error> {
+    let row = db.query_opt(
+        "SELECT t.id, t.user_id, u.email, u.role, t.family_id, t.rotated_at IS NOT NULL, t.revoked_at IS NOT NULL, t.expires_at <= now() \
+         FROM refresh_tokens t JOIN users u ON u.id = t.user_id \
+         WHERE t.token_hash = $1 FOR UPDATE OF t",
+        &[&token_hash],
+    ).await?;
+
+    Ok(row.map(|row| RefreshToken {
+        id: row.get(0),
+        user_id: row.get(1),
+        email: row.get(2),
+        role: row.get(3),
+        family_id: row.get(4),
+        rotated: row.get(5),
+        revoked: row.get(6),
+        expired: row.get(7),
+    }))
+}
+
+pub async fn mark_refresh_token_rotated(id: &i32, db: &impl GenericClient) -> Result<(), Error> {
+    db.execute("UPDATE refresh_tokens SET rotated_at = now() WHERE id = $1", &[id]).await?;
+    Ok(())
+}
+
+pub async fn revoke_refresh_token_family(family_id: &str, db: &impl GenericClient) -> Result<u64, Error> {
+    db.execute(
+        "UPDATE refresh_tokens SET revoked_at = now() WHERE family_id = $1 AND revoked_at IS NULL",
+        &[&family_id],
+    ).await
+}
+
+// Revokes the family of the token, only when it belongs to the user with this email
+pub async fn revoke_refresh_token_family_of(token_hash: &str, email: &str, db: &impl GenericClient) -> Result<u64, Error> {
+    db.execute(
+        "UPDATE refresh_tokens SET revoked_at = now() \
+         WHERE revoked_at IS NULL AND family_id = ( \
+             SELECT t.family_id FROM refresh_tokens t JOIN users u ON u.id = t.user_id \
+             WHERE t.token_hash = $1 AND u.email = $2)",
+        &[&token_hash, &email],
+    ).await
+}
+
+pub async fn insert_revoked_token(jti: &str, exp: i64, db: &impl GenericClient) -> Result<(), Error> {
+    db.execute(
+        "INSERT INTO revoked_tokens (jti, expires_at) VALUES ($1, to_timestamp($2)) ON CONFLICT (jti) DO NOTHING",
+        &[&jti, &(exp as f64)],
+    ).await?;
+    Ok(())
+}
+
+pub async fn delete_expired_revoked_tokens(db: &impl GenericClient) -> Result<u64, Error> {
+    db.execute("DELETE FROM revoked_tokens WHERE expires_at <= now()", &[]).await
+}
+
+// Pairs of jti and exp of every revoked token that has not expired yet
+pub async fn get_revoked_tokens(db: &impl GenericClient) -> Result<Vec<(String, i64)>, Error> {
+    let rows = db.query(
+        "SELECT jti, extract(epoch FROM expires_at)::bigint FROM revoked_tokens WHERE expires_at > now()",
+        &[],
+    ).await?;
+    Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+}
+
+pub async fn revoke_user_refresh_tokens(user_id: &i32, db: &impl GenericClient) -> Result<u64, Error> {
+    db.execute(
+        "UPDATE refresh_tokens SET revoked_at = now() WHERE user_id = $1 AND revoked_at IS NULL",
+        &[user_id],
+    ).await
+}
+
+pub async fn upsert_session_revocation(user_id: &i32, revoked_before: i64, expires_at: i64, db: &impl GenericClient) -> Result<(), Error> {
+    db.execute(
+        "INSERT INTO session_revocations (user_id, revoked_before, expires_at) VALUES ($1, to_timestamp($2), to_timestamp($3)) \
+         ON CONFLICT (user_id) DO UPDATE SET \
+             revoked_before = GREATEST(session_revocations.revoked_before, EXCLUDED.revoked_before), \
+             expires_at = GREATEST(session_revocations.expires_at, EXCLUDED.expires_at)",
+        &[user_id, &(revoked_before as f64), &(expires_at as f64)],
+    ).await?;
+    Ok(())
+}
+
+pub async fn delete_expired_session_revocations(db: &impl GenericClient) -> Result<u64, Error> {
+    db.execute("DELETE FROM session_revocations WHERE expires_at <= now()", &[]).await
+}
+
+// Triples of user id, revoked_before and expires_at that have not expired yet
+pub async fn get_session_revocations(db: &impl GenericClient) -> Result<Vec<(i32, i64, i64)>, Error> {
+    let rows = db.query(
+        "SELECT user_id, extract(epoch FROM revoked_before)::bigint, extract(epoch FROM expires_at)::bigint \
+         FROM session_revocations WHERE expires_at > now()",
+        &[],
+    ).await?;
+    Ok(rows.iter().map(|row| (row.get(0), row.get(1), row.get(2))).collect())
+}
+
+pub async fn insert_password_reset_token(user_id: &i32, token_hash: &str, ttl: i64, db: &impl GenericClient) -> Result<(), Error> {
+    db.execute(
+        "INSERT INTO password_reset_tokens (user_id, token_hash, expires_at) \
+         VALUES ($1, $2, now() + make_interval(secs => $3))",
+        &[user_id, &token_hash, &(ttl as f64)],
+    ).await?;
+    Ok(())
+}
+
+// Owner of an unused and unexpired reset token, locking the token until the transaction ends
+pub async fn get_password_reset_for_update(token_hash: &str, db: &impl GenericClient) -> Result<Option<PasswordReset>, Error> {
+    let row = db.query_opt(
+        "SELECT u.id, u.name, u.email FROM password_reset_tokens t JOIN users u ON u.id = t.user_id \
+         WHERE t.token_hash = $1 AND t.used_at IS NULL AND t.expires_at > now() FOR UPDATE OF t",
+        &[&token_hash],
+    ).await?;
+    Ok(row.map(|row| PasswordReset {
+        user_id: row.get(0),
+        name: row.get(1),
+        email: row.get(2),
+    }))
+}
+
+// Marks every outstanding reset token of the user as used, not only the one just redeemed
+pub async fn use_password_reset_tokens(user_id: &i32, db: &impl GenericClient) -> Result<u64, Error> {
+    db.execute(
+        "UPDATE password_reset_tokens SET used_at = now() WHERE user_id = $1 AND used_at IS NULL",
+        &[user_id],
+    ).await
+}
+
+// Starts or restarts an enrolment, returns 0 when two-factor authentication is already enabled
+pub async fn upsert_mfa_secret(user_id: &i32, secret: &str, db: &impl GenericClient) -> Result<u64, Error> {
+    db.execute(
+        "INSERT INTO user_mfa (user_id, secret) VALUES ($1, $2) \
+         ON CONFLICT (user_id) DO UPDATE SET secret = EXCLUDED.secret, last_used_step = NULL, created_at = now() \
+         WHERE user_mfa.enabled_at IS NULL",
+        &[user_id, &secret],
+    ).await
+}
+
+pub async fn get_mfa(user_id: &i32, db: &impl GenericClient) -> Result<Option<Mfa>, Error> {
+    let row = db.query_opt(
+        "SELECT secret, enabled_at IS NOT NULL FROM user_mfa WHERE user_id = $1",
+        &[user_id],
+    ).await?;
+    Ok(row.map(|row| Mfa {
+        secret: row.get(0),
+        enabled: row.get(1),
+    }))
+}
+
+pub async fn enable_mfa(user_id: &i32, step: i64, db: &impl GenericClient) -> Result<u64, Error> {
+    db.execute(
+        "UPDATE user_mfa SET enabled_at = now(), last_used_step = $2 WHERE user_id = $1 AND enabled_at IS NULL",
+        &[user_id, &step],
+    ).await
+}
+
+// Records the time step of an accepted code, returns 0 when it or a later one was already used
+pub async fn use_totp_step(user_id: &i32, step: i64, db: &impl GenericClient) -> Result<u64, Error> {
+    db.execute(
+        "UPDATE user_mfa SET last_used_step = $2 \
+         WHERE user_id = $1 AND enabled_at IS NOT NULL AND (last_used_step IS NULL OR last_used_step < $2)",
+        &[user_id, &step],
+    ).await
+}
+
+pub async fn replace_recovery_codes(user_id: &i32, code_hashes: &[String], db: &impl GenericClient) -> Result<(), Error> {
+    db.execute("DELETE FROM mfa_recovery_codes WHERE user_id = $1", &[user_id]).await?;
+    db.execute(
+        "INSERT INTO mfa_recovery_codes (user_id, code_hash) SELECT $1, unnest($2::text[])",
+        &[user_id, &code_hashes],
+    ).await?;
+    Ok(())
+}
+
+// Returns 0 when the code is unknown or already used
+pub async fn use_recovery_code(user_id: &i32, code_hash: &str, db: &impl GenericClient) -> Result<u64, Error> {
+    db.execute(
+        "UPDATE mfa_recovery_codes SET used_at = now() WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL",
+        &[user_id, &code_hash],
+    ).await
+}
+
+// Counters of the email and of the client IP, when there is one
+pub async fn get_login_attempts(email_key: &str, ip_key: Option<&str>, db: &impl GenericClient) -> Result<Vec<LoginAttempt>, Error> {
+    let rows = db.query(
+        "SELECT kind, failures, ceil(extract(epoch FROM locked_until))::bigint FROM login_attempts \
+         WHERE (kind = 'email' AND key = $1) OR (kind = 'ip' AND key = $2)",
+        &[&email_key, &ip_key],
+    ).await?;
+    Ok(rows.iter().map(|row| LoginAttempt {
+        kind: row.get(0),
+        failures: row.get(1),
+        locked_until: row.get(2),
+    }).collect())
+}
+
+// Counts one more attempt unless the key is locked, in a single statement so concurrent
+// attempts cannot all get past the lock. Returns the total, starting over when the last
+// attempt is older than window, or None when the key is locked.
+pub async fn reserve_login_attempt(kind: &str, key: &str, window: i64, db: &impl GenericClient) -> Result<Option<i32>, Error> {
+    let row = db.query_opt(
+        "INSERT INTO login_attempts (kind, key, failures, last_failure_at) VALUES ($1, $2, 1, now()) \
+         ON CONFLICT (kind, key) DO UPDATE SET \
+             failures = CASE WHEN login_attempts.last_failure_at < now() - make_interval(secs => $3) \
+                 THEN 1 ELSE login_attempts.failures + 1 END, \
+             last_failure_at = now() \
+         WHERE login_attempts.locked_until IS NULL OR login_attempts.locked_until <= now() \
+         RETURNING failures",
+        &[&kind, &key, &(window as f64)],
+    ).await?;
+    Ok(row.map(|row| row.get(0)))
+}
+
+// Takes back an attempt that turned out not to be a failure, unlocking the key once it is
+// within its free attempts again
+pub async fn release_login_attempt(kind: &str, key: &str, free_attempts: i32, db: &impl GenericClient) -> Result<u64, Error> {
+    db.execute(
+        "UPDATE login_attempts SET failures = GREATEST(failures - 1, 0), \
+             locked_until = CASE WHEN failures - 1 <= $3 THEN NULL ELSE locked_until END \
+         WHERE kind = $1 AND key = $2",
+        &[&kind, &key, &free_attempts],
+    ).await
+}
+
+pub async fn lock_login_key(kind: &str, key: &str, seconds: i64, db: &impl GenericClient) -> Result<(), Error> {
+    db.execute(
+        "UPDATE login_attempts SET locked_until = now() + make_interval(secs => $3) WHERE kind = $1 AND key = $2",
+        &[&kind, &key, &(seconds as f64)],
+    ).await?;
+    Ok(())
+}
+
+pub async fn delete_login_attempts(kind: &str, key: &str, db: &impl GenericClient) -> Result<u64, Error> {
+    db.execute("DELETE FROM login_attempts WHERE kind = $1 AND key = $2", &[&kind, &key]).await
+}
+
+// Counters that neither lock anything nor count towards the next failure anymore
+pub async fn delete_stale_login_attempts(window: i64, db: &impl GenericClient) -> Result<u64, Error> {
+    db.execute(
+        "DELETE FROM login_attempts WHERE last_failure_at < now() - make_interval(secs => $1) \
+         AND (locked_until IS NULL OR locked_until < now())",
+        &[&(window as f64)],
+    ).await
+}
\ No newline at end of file
@@ -0,0 +1,40 @@
+use tokio_postgres::GenericClient;
+use log::{error, info};
+use crate::AppState;
+use crate::libs::error::AppError;
+use crate::libs::token::ACCESS_TOKEN_TTL;
+use super::repository::{revoke_user_refresh_tokens, upsert_session_revocation};
+
+// Revocation of every session of a user, stored by revoke_all_sessions
+pub struct SessionRevocation {
+    user_id: i32,
+    revoked_before: i64,
+    expires_at: i64,
+}
+
+impl SessionRevocation {
+    // Starts rejecting the access tokens in this process. Called once the transaction that
+    // stored the revocation has committed, so a failed commit leaves the sessions working.
+    pub fn apply(&self, state: &AppState) {
+        state.revoked_tokens.revoke_user(self.user_id, self.revoked_before, self.expires_at);
+        info!("Revoked all sessions of user {}", self.user_id);
+    }
+}
+
+// Ends every session of the user: refresh tokens are revoked and access tokens issued
+// until now are rejected until they expire on their own. Only the database is changed,
+// the returned revocation is applied once the caller has committed.
+pub async fn revoke_all_sessions(user_id: i32, db: &impl GenericClient) -> Result<SessionRevocation, AppError> {
+    let now = chrono::Utc::now().timestamp();
+    let expires_at = now + ACCESS_TOKEN_TTL;
+
+    if let Err(e) = revoke_user_refresh_tokens(&user_id, db).await {
+        error!("Error revoking refresh tokens of user {}: {:?}", user_id, e);
+        return Err(AppError::Internal("Internal error".to_string()));
+    }
+    if let Err(e) = upsert_session_revocation(&user_id, now, expires_at, db).await {
+        error!("Error revoking sessions of user {}: {:?}", user_id, e);
+        return Err(AppError::Internal("Internal error".to_string()));
+    }
+    Ok(SessionRevocation { user_id, revoked_before: now, expires_at })
+}
\ No newline at end of file
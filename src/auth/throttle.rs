@@ -0,0 +1,175 @@
+use std::net::IpAddr;
+use tokio_postgres::{Client, GenericClient};
+use log::{error, info};
+use crate::libs::config::LoginThrottleConfig;
+use crate::libs::error::AppError;
+use super::repository::{delete_login_attempts, get_login_attempts, lock_login_key, release_login_attempt, reserve_login_attempt};
+
+const EMAIL: &str = "email";
+const IP: &str = "ip";
+
+// Counts the attempt against the email and the client IP before the credentials are
+// checked, refusing it while either is locked. Counting and locking happen in one
+// transaction holding the rows, so parallel attempts cannot all get past the lock, and
+// every attempt stays a failure until it is released or cleared. A locked account answers
+// the same whether it exists or not.
+pub async fn begin(config: &LoginThrottleConfig, email: &str, ip: Option<IpAddr>, db: &mut Client) -> Result<(), AppError> {
+    let tx = match db.transaction().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {:?}", e);
+            return Err(AppError::Internal("Internal error".to_string()));
+        }
+    };
+    // A refused attempt is rolled back with the transaction
+    reserve_attempt(config, &email_key(email), ip.map(|ip| ip.to_string()), &tx).await?;
+    if let Err(e) = tx.commit().await {
+        error!("Failed to commit transaction: {:?}", e);
+        return Err(AppError::Internal("Internal error".to_string()));
+    }
+    Ok(())
+}
+
+async fn reserve_attempt(config: &LoginThrottleConfig, email_key: &str, ip_key: Option<String>, db: &impl GenericClient) -> Result<(), AppError> {
+    let mut keys = vec![(EMAIL, email_key.to_string(), config.free_attempts)];
+    if let Some(ip_key) = &ip_key {
+        keys.push((IP, ip_key.clone(), config.ip_free_attempts));
+    }
+
+    let mut counted = Vec::new();
+    for (kind, key, free_attempts) in keys {
+        match reserve_login_attempt(kind, &key, config.failure_window, db).await {
+            Ok(Some(failures)) => counted.push((kind, key, failures, free_attempts)),
+            Ok(None) => return Err(refusal(config, email_key, ip_key.as_deref(), db).await),
+            Err(e) => {
+                error!("Error recording login attempt: {:?}", e);
+                return Err(AppError::Internal("Internal error".to_string()));
+            }
+        }
+    }
+
+    // Locks the keys for the attempts after this one
+    for (kind, key, failures, free_attempts) in counted {
+        let delay = penalty(config, failures, free_attempts, kind == EMAIL);
+        if delay == 0 {
+            continue;
+        }
+        if kind == EMAIL && failures >= config.lockout_threshold {
+            info!("Locking {} for {} seconds after {} failed logins", key, delay, failures);
+        }
+        if let Err(e) = lock_login_key(kind, &key, delay, db).await {
+            error!("Error locking login key: {:?}", e);
+            return Err(AppError::Internal("Internal error".to_string()));
+        }
+    }
+    Ok(())
+}
+
+// Takes back the attempt of the client IP once the credentials are correct. The attempt of
+// the email is only forgotten with clear, once a session is issued.
+pub async fn release_ip(config: &LoginThrottleConfig, ip: Option<IpAddr>, db: &impl GenericClient) -> Result<(), AppError> {
+    let ip = match ip {
+        Some(ip) => ip.to_string(),
+        None => return Ok(()),
+    };
+    match release_login_attempt(IP, &ip, config.ip_free_attempts, db).await {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!("Error releasing login attempt: {:?}", e);
+            Err(AppError::Internal("Internal error".to_string()))
+        }
+    }
+}
+
+// Error of an attempt refused because the email or the client IP is locked
+async fn refusal(config: &LoginThrottleConfig, email_key: &str, ip_key: Option<&str>, db: &impl GenericClient) -> AppError {
+    let attempts = match get_login_attempts(email_key, ip_key, db).await {
+        Ok(attempts) => attempts,
+        Err(e) => {
+            error!("Error getting login attempts: {:?}", e);
+            return AppError::Internal("Internal error".to_string());
+        }
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    let mut throttled = 1;
+    for attempt in attempts {
+        let remaining = match attempt.locked_until {
+            Some(locked_until) if locked_until > now => (locked_until - now) as u64,
+            _ => continue,
+        };
+        if attempt.kind == EMAIL && attempt.failures >= config.lockout_threshold {
+            return AppError::AccountLocked(remaining);
+        }
+        throttled = throttled.max(remaining);
+    }
+    AppError::LoginThrottled(throttled)
+}
+
+// Forgets the failures of the email, after a successful login or when an admin unlocks it.
+// Counters of client IPs only expire.
+pub async fn clear(email: &str, db: &impl GenericClient) -> Result<(), AppError> {
+    match delete_login_attempts(EMAIL, &email_key(email), db).await {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!("Error clearing login attempts: {:?}", e);
+            Err(AppError::Internal("Internal error".to_string()))
+        }
+    }
+}
+
+// Case does not make a different counter
+fn email_key(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+// Seconds the key is locked after its latest failure
+fn penalty(config: &LoginThrottleConfig, failures: i32, free_attempts: i32, lockable: bool) -> i64 {
+    if lockable && failures >= config.lockout_threshold {
+        return config.lockout_duration;
+    }
+    if failures <= free_attempts {
+        return 0;
+    }
+    let doublings = (failures - free_attempts - 1).min(30) as u32;
+    config.backoff_base.saturating_mul(1 << doublings).min(config.backoff_max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> LoginThrottleConfig {
+        LoginThrottleConfig {
+            free_attempts: 3,
+            ip_free_attempts: 20,
+            backoff_base: 1,
+            backoff_max: 300,
+            lockout_threshold: 10,
+            lockout_duration: 900,
+            failure_window: 3600,
+        }
+    }
+
+    #[test]
+    fn free_attempts_have_no_delay() {
+        assert_eq!(penalty(&config(), 3, 3, true), 0);
+    }
+
+    #[test]
+    fn delay_doubles_after_free_attempts() {
+        let delays: Vec<i64> = (4..=9).map(|failures| penalty(&config(), failures, 3, true)).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 32]);
+    }
+
+    #[test]
+    fn email_is_locked_at_threshold() {
+        assert_eq!(penalty(&config(), 10, 3, true), 900);
+    }
+
+    #[test]
+    fn ip_is_never_locked_and_delay_is_capped() {
+        assert_eq!(penalty(&config(), 40, 20, false), 300);
+        assert_eq!(penalty(&config(), 1000, 20, false), 300);
+    }
+}
\ No newline at end of file
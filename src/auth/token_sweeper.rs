@@ -0,0 +1,70 @@
+use std::sync::Arc;
+use deadpool_postgres::Pool;
+use tokio::time::{interval_at, Duration, Instant};
+use log::{debug, error};
+use crate::AppState;
+use crate::libs::revocation::RevocationList;
+use super::repository::{delete_expired_revoked_tokens, delete_stale_login_attempts, delete_expired_session_revocations, get_revoked_tokens, get_session_revocations};
+
+// Deletes revoked tokens past their exp and syncs the in-memory list with the database
+pub async fn sweep(pool: &Pool, revoked_tokens: &RevocationList) {
+    let db = match pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return;
+        }
+    };
+
+    match delete_expired_revoked_tokens(&**db).await {
+        Ok(deleted) => debug!("Swept {} expired revoked tokens", deleted),
+        Err(e) => error!("Error deleting expired revoked tokens: {:?}", e),
+    }
+
+    if let Err(e) = delete_expired_session_revocations(&**db).await {
+        error!("Error deleting expired session revocations: {:?}", e);
+    }
+
+    let entries = match get_revoked_tokens(&**db).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Error loading revoked tokens: {:?}", e);
+            return;
+        }
+    };
+    let users = match get_session_revocations(&**db).await {
+        Ok(users) => users,
+        Err(e) => {
+            error!("Error loading session revocations: {:?}", e);
+            return;
+        }
+    };
+    revoked_tokens.merge(entries, users, chrono::Utc::now().timestamp());
+}
+
+// Deletes failed login counters past LOGIN_FAILURE_WINDOW that lock nothing anymore
+async fn sweep_login_attempts(pool: &Pool, window: i64) {
+    let db = match pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return;
+        }
+    };
+
+    match delete_stale_login_attempts(window, &**db).await {
+        Ok(deleted) => debug!("Swept {} stale login attempts", deleted),
+        Err(e) => error!("Error deleting stale login attempts: {:?}", e),
+    }
+}
+
+// Background task sweeping every REVOCATION_SWEEP_INTERVAL seconds
+pub async fn run(state: Arc<AppState>) {
+    let period = Duration::from_secs(state.config.token.revocation_sweep_interval.max(1));
+    let mut ticker = interval_at(Instant::now() + period, period);
+    loop {
+        ticker.tick().await;
+        sweep(&state.db_pool, &state.revoked_tokens).await;
+        sweep_login_attempts(&state.db_pool, state.config.login_throttle.failure_window).await;
+    }
+}
\ No newline at end of file
@@ -0,0 +1,31 @@
+use log::error;
+use crate::AppState;
+use crate::libs::mailer::{send_in_background, Email};
+use crate::libs::token::claim_action_token;
+
+pub const VERIFY_EMAIL_PURPOSE: &str = "verify_email";
+
+// Mails the link of GET /auth/verify-email for the current address of the user. The email
+// is sent in the background and failures are only logged, a lost email can be sent again
+// with the resend endpoint.
+pub fn send_verification_email(state: &AppState, user_id: i32, name: &str, email: &str) {
+    let ttl = state.config.verification.ttl;
+    let token = match claim_action_token(VERIFY_EMAIL_PURPOSE, user_id, email.to_string(), ttl, &state.keyring) {
+        Ok(token) => token,
+        Err(e) => {
+            error!("Error claiming verification token: {}", e);
+            return;
+        }
+    };
+
+    let link = format!("{}/auth/verify-email?token={}", state.config.mail.base_url.trim_end_matches('/'), token);
+    let email = Email {
+        to: email.to_string(),
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "Hi {},\n\nOpen the link below to verify your email address. It expires in {} hours.\n\n{}\n",
+            name, ttl / 3600, link
+        ),
+    };
+    send_in_background(&state.mailer, email);
+}
\ No newline at end of file
@@ -1,33 +1,44 @@
+pub mod admission;
+pub mod config;
+pub mod error;
+pub mod keyring;
+pub mod mailer;
+pub mod pagination;
+pub mod password;
+pub mod rate_limit;
+pub mod request;
+pub mod response;
+pub mod revocation;
+pub mod role;
+pub mod router;
 pub mod token;
+pub mod totp;
+pub mod validation;
 use std::env;
 use anyhow::{Result, Error};
-
-pub const OK_RESPONSE: &str = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n";
-pub const NOT_FOUND: &str = "HTTP/1.1 404 NOT FOUND\r\n\r\n";
-pub const NO_CONTENT: &str = "HTTP/1.1 204 NO CONTENT\r\n\r\n";
-pub const BAD_REQUEST: &str = "HTTP/1.1 400 BAD REQUEST\r\n\r\n";
-pub const UNAUTHORIZED: &str = "HTTP/1.1 401 UNAUTHORIZED\r\n\r\n";
-pub const INTERNAL_ERROR: &str = "HTTP/1.1 500 INTERNAL ERROR\r\n\r\n";
-pub const TOO_MANY_REQUEST: &str = "HTTP/1.1 429 TOO MANY REQUESTS\r\n\r\n";
-pub const CORS_ALLOW_ALL: &str = "HTTP/1.1 200 OK\r\nAccess-Control-Allow-Origin: *\r\nAccess-Control-Allow-Methods: GET, POST, PUT, DELETE, OPTIONS\r\nAccess-Control-Allow-Headers: Content-Type\r\n\r\n";
-
-//Get id from request URL
-pub fn get_id(request: &str) -> &str {
-    request.split("/").nth(2).unwrap_or_default().split_whitespace().next().unwrap_or_default()
-}
+use request::Request;
+use keyring::Keyring;
+use revocation::RevocationList;
+use token::AuthContext;
 
 pub fn get_db_url() -> String {
     env::var("DATABASE_URL").expect("DATABASE_URL must be set")
 }
 
-pub async fn authenticate(request: &str) -> Result<String, Error> {
+pub async fn authenticate(request: &Request, keyring: &Keyring, revoked_tokens: &RevocationList) -> Result<AuthContext, Error> {
     let token = request
-        .split("\r\n")
-        .find(|s| s.starts_with("Authorization: Bearer "))
-        .and_then(|s| s.split_whitespace().nth(2))
+        .header("Authorization")
+        .and_then(|s| s.strip_prefix("Bearer "))
+        .map(|s| s.trim())
         .ok_or_else(|| Error::msg("Authorization header not found"))?;
-    match token::validate_token(token) {
-        Ok(email) => Ok(email),
+    match token::validate_token(token, keyring) {
+        Ok(claims) => {
+            let auth = AuthContext::try_from(claims).map_err(Error::msg)?;
+            if revoked_tokens.is_revoked(&auth) {
+                return Err(Error::msg("Token has been revoked"));
+            }
+            Ok(auth)
+        }
         Err(e) => Err(anyhow::Error::msg(e.to_string())), // Ubah ke tipe error yang mendukung Send + Sync
     }
     
@@ -0,0 +1,152 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use governor::{DefaultDirectRateLimiter, Quota, RateLimiter};
+use log::debug;
+use tokio::sync::{Mutex, Semaphore, SemaphorePermit};
+use tokio::time::timeout;
+use super::config::AdmissionConfig;
+use super::error::AppError;
+
+// Decides whether the server takes on a request. A request starts at once when a slot is
+// free and the global rate allows it, otherwise it waits in a bounded queue for at most
+// max_wait. Requests that find the queue full or wait too long get a 503 with Retry-After.
+pub struct Admission {
+    slots: Semaphore,
+    rate: DefaultDirectRateLimiter,
+    // Queued requests wait for a rate token one at a time, in the order they got their slot
+    rate_turn: Mutex<()>,
+    waiting: AtomicUsize,
+    queue_size: usize,
+    max_wait: Duration,
+}
+
+// Takes a place in the queue and gives it back when dropped, whether the request was
+// admitted, rejected or its connection went away
+struct QueuePlace<'a>(&'a AtomicUsize);
+
+impl Drop for QueuePlace<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl Admission {
+    pub fn from_config(config: &AdmissionConfig) -> Self {
+        Admission {
+            slots: Semaphore::new(config.max_concurrent.max(1)),
+            rate: RateLimiter::direct(Quota::per_second(config.rate_per_second)),
+            rate_turn: Mutex::new(()),
+            waiting: AtomicUsize::new(0),
+            queue_size: config.queue_size,
+            max_wait: Duration::from_millis(config.max_wait_ms),
+        }
+    }
+
+    // The slot is held until the returned permit is dropped
+    pub async fn admit(&self) -> Result<SemaphorePermit<'_>, AppError> {
+        // Only when nobody is queued, a new request must not take the slot or rate token
+        // a waiting one is about to get
+        if self.waiting.load(Ordering::Acquire) == 0 {
+            if let Ok(permit) = self.slots.try_acquire() {
+                if self.rate.check().is_ok() {
+                    return Ok(permit);
+                }
+            }
+        }
+
+        let place = self.enqueue().ok_or_else(|| {
+            debug!("Admission queue is full ({} waiting)", self.queue_size);
+            self.overloaded()
+        })?;
+        // The slot comes first, so a rate token is only taken by a request that can start
+        let admitted = timeout(self.max_wait, async {
+            let permit = self.slots.acquire().await.expect("Admission semaphore closed");
+            let _turn = self.rate_turn.lock().await;
+            self.rate.until_ready().await;
+            permit
+        })
+        .await;
+        drop(place);
+
+        admitted.map_err(|_| {
+            debug!("Request waited {:?} without being admitted", self.max_wait);
+            self.overloaded()
+        })
+    }
+
+    fn enqueue(&self) -> Option<QueuePlace<'_>> {
+        self.waiting
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |waiting| {
+                (waiting < self.queue_size).then_some(waiting + 1)
+            })
+            .ok()
+            .map(|_| QueuePlace(&self.waiting))
+    }
+
+    // A request queued now would be served within max_wait, so that is when to come back
+    fn overloaded(&self) -> AppError {
+        let seconds = self.max_wait.as_secs() + u64::from(self.max_wait.subsec_nanos() > 0);
+        AppError::Overloaded(seconds.max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+    use super::*;
+
+    fn admission(max_concurrent: usize, queue_size: usize, max_wait_ms: u64) -> Admission {
+        admission_with_rate(max_concurrent, 1000, queue_size, max_wait_ms)
+    }
+
+    fn admission_with_rate(max_concurrent: usize, rate_per_second: u32, queue_size: usize, max_wait_ms: u64) -> Admission {
+        Admission::from_config(&AdmissionConfig {
+            max_concurrent,
+            rate_per_second: NonZeroU32::new(rate_per_second).unwrap(),
+            queue_size,
+            max_wait_ms,
+            max_connections: 1024,
+        })
+    }
+
+    #[tokio::test]
+    async fn rejects_when_queue_is_full() {
+        let admission = admission(1, 0, 1000);
+        let _permit = admission.admit().await.unwrap();
+        assert!(matches!(admission.admit().await, Err(AppError::Overloaded(1))));
+    }
+
+    #[tokio::test]
+    async fn rejects_after_max_wait() {
+        let admission = admission(1, 1, 50);
+        let _permit = admission.admit().await.unwrap();
+        assert!(matches!(admission.admit().await, Err(AppError::Overloaded(1))));
+        assert_eq!(admission.waiting.load(Ordering::Acquire), 0);
+    }
+
+    #[tokio::test]
+    async fn queued_request_gets_released_slot() {
+        let admission = admission(1, 1, 1000);
+        let permit = admission.admit().await.unwrap();
+        let (queued, _) = tokio::join!(admission.admit(), async { drop(permit) });
+        assert!(queued.is_ok());
+    }
+
+    #[tokio::test]
+    async fn new_request_does_not_overtake_queued_one() {
+        // One request per second, the first takes the only token of this second
+        let admission = admission_with_rate(10, 1, 10, 3000);
+        drop(admission.admit().await.unwrap());
+
+        let queued = admission.admit();
+        tokio::pin!(queued);
+        assert!(timeout(Duration::from_millis(50), &mut queued).await.is_err());
+
+        // The next token is free by now, but it belongs to the queued request
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        let newcomer = admission.admit();
+        tokio::pin!(newcomer);
+        assert!(timeout(Duration::from_millis(50), &mut newcomer).await.is_err());
+        assert!(queued.await.is_ok());
+    }
+}
@@ -0,0 +1,271 @@
+use std::env;
+use std::num::NonZeroU32;
+use std::str::FromStr;
+use std::time::Duration;
+use jsonwebtoken::Algorithm;
+use super::mailer::MailTransport;
+use super::rate_limit::{KeyKind, RateLimitBackend, RateLimitRule};
+
+// Settings read once from the environment at startup
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub password: PasswordConfig,
+    pub token: TokenConfig,
+    pub registration: RegistrationConfig,
+    pub verification: VerificationConfig,
+    pub password_reset: PasswordResetConfig,
+    pub mfa: MfaConfig,
+    pub login_throttle: LoginThrottleConfig,
+    pub rate_limit: RateLimitConfig,
+    pub admission: AdmissionConfig,
+    pub mail: MailConfig,
+}
+
+#[derive(Debug, Clone)]
+pub struct PasswordConfig {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_uppercase: bool,
+    pub require_lowercase: bool,
+    pub require_digit: bool,
+    pub require_special: bool,
+    // Local file with one banned password per line, none when set to an empty value
+    pub banned_list_file: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TokenConfig {
+    // Algorithm and key id of the tokens being issued
+    pub algorithm: Algorithm,
+    pub key_id: String,
+    // Shared secret of the HS* algorithms
+    pub secret_key: Option<String>,
+    // PEM keys of the RS*, PS*, ES* and EdDSA algorithms
+    pub private_key_file: Option<String>,
+    pub public_key_file: Option<String>,
+    // Older keys still accepted during a rotation, each written as kid:ALGORITHM:path
+    pub verification_keys: Vec<String>,
+    // Expected iss and aud claims, set on issued tokens and checked on validation
+    pub issuer: String,
+    pub audience: String,
+    // Lifetime of a refresh token in seconds
+    pub refresh_ttl: i64,
+    // Seconds between two sweeps of expired revoked tokens
+    pub revocation_sweep_interval: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct RegistrationConfig {
+    // Accounts made through POST /auth/register start with an unverified email
+    pub require_verification: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct VerificationConfig {
+    // Lifetime of an email verification link in seconds
+    pub ttl: i64,
+    // Reject logins of users whose email is not verified yet
+    pub required_for_login: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PasswordResetConfig {
+    // Lifetime of a password reset token in seconds
+    pub ttl: i64,
+    // Page of the client that asks for the new password, the token is appended as ?token=
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MfaConfig {
+    // Issuer shown by authenticator apps next to the account
+    pub issuer: String,
+    // Lifetime in seconds of the challenge token between the two login steps
+    pub challenge_ttl: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoginThrottleConfig {
+    // Failed logins accepted without delay, per email and per client IP
+    pub free_attempts: i32,
+    pub ip_free_attempts: i32,
+    // Delay after the first failure past the free ones, doubled by every further failure
+    pub backoff_base: i64,
+    pub backoff_max: i64,
+    // Failures of one email that lock its account, and for how many seconds
+    pub lockout_threshold: i32,
+    pub lockout_duration: i64,
+    // Seconds without a failure after which the counters start over
+    pub failure_window: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    pub store: RateLimitBackend,
+    // Quotas of the Limiter classes
+    pub common: RateLimitRule,
+    pub hard: RateLimitRule,
+    pub register: RateLimitRule,
+    // Quotas of single routes, each written as METHOD /template=rule
+    pub routes: Vec<(String, RateLimitRule)>,
+    // API keys counted under their own key by @api_key quotas, other callers are counted per IP
+    pub api_keys: Vec<String>,
+    // Clients tracked by one in-memory limiter before new ones share a single quota
+    pub max_keys: usize,
+    // Seconds between two evictions of idle clients
+    pub sweep_interval: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct AdmissionConfig {
+    // Requests handled at the same time
+    pub max_concurrent: usize,
+    // Requests started per second over the whole server
+    pub rate_per_second: NonZeroU32,
+    // Requests allowed to wait for a slot, further ones are rejected at once
+    pub queue_size: usize,
+    // Milliseconds a request may wait for a slot before it is rejected
+    pub max_wait_ms: u64,
+    // Open connections, further ones wait in the listen backlog until one closes
+    pub max_connections: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct MailConfig {
+    pub transport: MailTransport,
+    // Target of the file transport
+    pub file: Option<String>,
+    pub from: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    // Public address of the API, used to build links in emails
+    pub base_url: String,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Config {
+            password: PasswordConfig {
+                min_length: env_or("PASSWORD_MIN_LENGTH", 10),
+                // bcrypt only uses the first 72 bytes of a password
+                max_length: env_or("PASSWORD_MAX_LENGTH", 72),
+                require_uppercase: env_or("PASSWORD_REQUIRE_UPPERCASE", true),
+                require_lowercase: env_or("PASSWORD_REQUIRE_LOWERCASE", true),
+                require_digit: env_or("PASSWORD_REQUIRE_DIGIT", true),
+                require_special: env_or("PASSWORD_REQUIRE_SPECIAL", true),
+                banned_list_file: Some(env_or("PASSWORD_BANNED_LIST_FILE", "config/banned_passwords.txt".to_string()))
+                    .filter(|path| !path.is_empty()),
+            },
+            token: TokenConfig {
+                algorithm: env_or("JWT_ALGORITHM", Algorithm::HS256),
+                key_id: env_or("JWT_KEY_ID", "default".to_string()),
+                secret_key: env::var("SECRET_KEY").ok(),
+                private_key_file: env::var("JWT_PRIVATE_KEY_FILE").ok(),
+                public_key_file: env::var("JWT_PUBLIC_KEY_FILE").ok(),
+                verification_keys: env_list("JWT_VERIFICATION_KEYS"),
+                issuer: env_or("JWT_ISSUER", "crud-api".to_string()),
+                audience: env_or("JWT_AUDIENCE", "crud-api".to_string()),
+                refresh_ttl: env_or("REFRESH_TOKEN_TTL", 30 * 24 * 60 * 60),
+                revocation_sweep_interval: env_or("REVOCATION_SWEEP_INTERVAL", 60),
+            },
+            registration: RegistrationConfig {
+                require_verification: env_or("REGISTER_REQUIRE_VERIFICATION", false),
+            },
+            verification: VerificationConfig {
+                ttl: env_or("EMAIL_VERIFICATION_TTL", 24 * 60 * 60),
+                required_for_login: env_or("LOGIN_REQUIRE_VERIFIED_EMAIL", false),
+            },
+            password_reset: PasswordResetConfig {
+                ttl: env_or("PASSWORD_RESET_TTL", 60 * 60),
+                url: env::var("PASSWORD_RESET_URL").ok(),
+            },
+            mfa: MfaConfig {
+                issuer: env_or("MFA_ISSUER", "crud-api".to_string()),
+                challenge_ttl: env_or("MFA_CHALLENGE_TTL", 5 * 60),
+            },
+            login_throttle: LoginThrottleConfig {
+                free_attempts: env_or("LOGIN_FREE_ATTEMPTS", 3),
+                ip_free_attempts: env_or("LOGIN_IP_FREE_ATTEMPTS", 20),
+                backoff_base: env_or("LOGIN_BACKOFF_BASE", 1),
+                backoff_max: env_or("LOGIN_BACKOFF_MAX", 5 * 60),
+                lockout_threshold: env_or("LOGIN_LOCKOUT_THRESHOLD", 10),
+                lockout_duration: env_or("LOGIN_LOCKOUT_DURATION", 15 * 60),
+                failure_window: env_or("LOGIN_FAILURE_WINDOW", 60 * 60),
+            },
+            rate_limit: RateLimitConfig {
+                store: env_or("RATE_LIMIT_STORE", RateLimitBackend::Memory),
+                common: env_or("RATE_LIMIT_COMMON", "100/s@user".parse().unwrap()),
+                hard: env_or("RATE_LIMIT_HARD", "10/s@ip".parse().unwrap()),
+                // REGISTER_RATE_LIMIT_PER_MINUTE is still read for existing deployments
+                register: env_or("RATE_LIMIT_REGISTER", RateLimitRule {
+                    count: env_or("REGISTER_RATE_LIMIT_PER_MINUTE", NonZeroU32::new(5).unwrap()),
+                    period: Duration::from_secs(60),
+                    key: KeyKind::Ip,
+                }),
+                routes: env_route_rules("RATE_LIMIT_ROUTES"),
+                api_keys: env_list("RATE_LIMIT_API_KEYS"),
+                max_keys: env_or("RATE_LIMIT_MAX_KEYS", 100_000),
+                sweep_interval: env_or("RATE_LIMIT_SWEEP_INTERVAL", 60),
+            },
+            admission: AdmissionConfig {
+                max_concurrent: env_or("ADMISSION_MAX_CONCURRENT", 10),
+                rate_per_second: env_or("ADMISSION_RATE_PER_SECOND", NonZeroU32::new(200).unwrap()),
+                queue_size: env_or("ADMISSION_QUEUE_SIZE", 100),
+                max_wait_ms: env_or("ADMISSION_MAX_WAIT_MS", 2000),
+                max_connections: env_or("ADMISSION_MAX_CONNECTIONS", 1024),
+            },
+            mail: MailConfig {
+                transport: env_or("MAILER", MailTransport::Log),
+                file: env::var("MAIL_FILE").ok(),
+                from: env_or("MAIL_FROM", "no-reply@localhost".to_string()),
+                smtp_host: env_or("SMTP_HOST", "localhost".to_string()),
+                smtp_port: env_or("SMTP_PORT", 25),
+                smtp_username: env::var("SMTP_USERNAME").ok(),
+                smtp_password: env::var("SMTP_PASSWORD").ok(),
+                base_url: env_or("APP_BASE_URL", "http://localhost:8080".to_string()),
+            },
+        }
+    }
+}
+
+// Read an environment variable, falling back to the default when it is not set
+pub fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} has an invalid value '{}'", name, value)),
+        Err(_) => default,
+    }
+}
+
+// Read a comma separated environment variable, empty when it is not set
+pub fn env_list(name: &str) -> Vec<String> {
+    env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+// Read a comma separated list of METHOD /template=rule entries
+fn env_route_rules(name: &str) -> Vec<(String, RateLimitRule)> {
+    env_list(name)
+        .into_iter()
+        .map(|entry| {
+            let (route, rule) = entry
+                .split_once('=')
+                .unwrap_or_else(|| panic!("{} has an invalid entry '{}'", name, entry));
+            let (method, template) = route
+                .trim()
+                .split_once(' ')
+                .unwrap_or_else(|| panic!("{} has an invalid route '{}'", name, route));
+            let rule = rule
+                .parse()
+                .unwrap_or_else(|e| panic!("{} has an invalid entry '{}': {}", name, entry, e));
+            (format!("{} {}", method.to_ascii_uppercase(), template.trim()), rule)
+        })
+        .collect()
+}
\ No newline at end of file
@@ -0,0 +1,133 @@
+use std::fmt;
+use super::response::{Response, StatusCode};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ErrorDetail {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    pub message: String,
+}
+
+// Error shared by every handler. It is rendered as
+// {"error": {"code": ..., "message": ..., "details": [...]}} with the status of the variant.
+#[derive(Debug)]
+pub enum AppError {
+    BadRequest(String),
+    Validation(Vec<ErrorDetail>),
+    Unauthorized(String),
+    Forbidden(String),
+    EmailNotVerified,
+    // Seconds until the account of the email is unlocked
+    AccountLocked(u64),
+    // Seconds until the next login attempt of the email or client is accepted
+    LoginThrottled(u64),
+    NotFound(String),
+    MethodNotAllowed,
+    PayloadTooLarge,
+    TooManyRequests,
+    HeaderTooLarge,
+    NotImplemented(String),
+    Internal(String),
+    // Seconds after which the server may have room for the request again
+    Overloaded(u64),
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BadRequest,
+            AppError::Validation(_) => StatusCode::UnprocessableEntity,
+            AppError::Unauthorized(_) => StatusCode::Unauthorized,
+            AppError::Forbidden(_) => StatusCode::Forbidden,
+            AppError::EmailNotVerified => StatusCode::Forbidden,
+            AppError::AccountLocked(_) => StatusCode::Locked,
+            AppError::LoginThrottled(_) => StatusCode::TooManyRequests,
+            AppError::NotFound(_) => StatusCode::NotFound,
+            AppError::MethodNotAllowed => StatusCode::MethodNotAllowed,
+            AppError::PayloadTooLarge => StatusCode::PayloadTooLarge,
+            AppError::TooManyRequests => StatusCode::TooManyRequests,
+            AppError::HeaderTooLarge => StatusCode::HeaderTooLarge,
+            AppError::NotImplemented(_) => StatusCode::NotImplemented,
+            AppError::Internal(_) => StatusCode::InternalError,
+            AppError::Overloaded(_) => StatusCode::ServiceUnavailable,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::BadRequest(_) => "bad_request",
+            AppError::Validation(_) => "validation_failed",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::EmailNotVerified => "email_not_verified",
+            AppError::AccountLocked(_) => "account_locked",
+            AppError::LoginThrottled(_) => "login_throttled",
+            AppError::NotFound(_) => "not_found",
+            AppError::MethodNotAllowed => "method_not_allowed",
+            AppError::PayloadTooLarge => "payload_too_large",
+            AppError::TooManyRequests => "too_many_requests",
+            AppError::HeaderTooLarge => "header_too_large",
+            AppError::NotImplemented(_) => "not_implemented",
+            AppError::Internal(_) => "internal_error",
+            AppError::Overloaded(_) => "overloaded",
+        }
+    }
+
+    pub fn details(&self) -> &[ErrorDetail] {
+        match self {
+            AppError::Validation(details) => details,
+            _ => &[],
+        }
+    }
+
+    // Value of the Retry-After header, in seconds
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            AppError::AccountLocked(seconds)
+            | AppError::LoginThrottled(seconds)
+            | AppError::Overloaded(seconds) => Some(*seconds),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::BadRequest(msg)
+            | AppError::Unauthorized(msg)
+            | AppError::Forbidden(msg)
+            | AppError::NotFound(msg)
+            | AppError::NotImplemented(msg)
+            | AppError::Internal(msg) => f.write_str(msg),
+            AppError::Validation(_) => f.write_str("Validation failed"),
+            AppError::MethodNotAllowed => f.write_str("Method not allowed"),
+            AppError::PayloadTooLarge => f.write_str("Payload too large"),
+            AppError::TooManyRequests => f.write_str("Too many requests"),
+            AppError::EmailNotVerified => f.write_str("Email address is not verified"),
+            AppError::AccountLocked(seconds) => write!(f, "Account is locked after too many failed logins, try again in {} seconds", seconds),
+            AppError::LoginThrottled(seconds) => write!(f, "Too many failed logins, try again in {} seconds", seconds),
+            AppError::HeaderTooLarge => f.write_str("Request header fields too large"),
+            AppError::Overloaded(seconds) => write!(f, "Server is busy, try again in {} seconds", seconds),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<AppError> for Response {
+    fn from(e: AppError) -> Self {
+        let body = serde_json::json!({
+            "error": {
+                "code": e.code(),
+                "message": e.to_string(),
+                "details": e.details(),
+            }
+        });
+        let response = Response::json(e.status(), body.to_string());
+        match e.retry_after() {
+            Some(seconds) => response.with_header("Retry-After", seconds.to_string()),
+            None => response,
+        }
+    }
+}
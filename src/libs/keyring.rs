@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::fs;
+use anyhow::{anyhow, Context, Result};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use super::config::TokenConfig;
+
+// Signing key of the issued tokens plus every key accepted when validating them,
+// loaded once at startup
+pub struct Keyring {
+    key_id: String,
+    algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    verification_keys: HashMap<String, (Algorithm, DecodingKey)>,
+    issuer: String,
+    audience: String,
+}
+
+impl Keyring {
+    pub fn from_config(config: &TokenConfig) -> Result<Self> {
+        let algorithm = config.algorithm;
+        let (encoding_key, decoding_key) = if is_hmac(algorithm) {
+            let secret = config.secret_key.as_ref().context("SECRET_KEY must be set")?;
+            (EncodingKey::from_secret(secret.as_bytes()), DecodingKey::from_secret(secret.as_bytes()))
+        } else {
+            let private_key_file = config.private_key_file.as_ref().context("JWT_PRIVATE_KEY_FILE must be set")?;
+            let public_key_file = config.public_key_file.as_ref().context("JWT_PUBLIC_KEY_FILE must be set")?;
+            (encoding_key(algorithm, &read_key(private_key_file)?)?, decoding_key(algorithm, &read_key(public_key_file)?)?)
+        };
+
+        let mut verification_keys = HashMap::new();
+        for entry in &config.verification_keys {
+            let (key_id, algorithm, key) = load_verification_key(entry)?;
+            verification_keys.insert(key_id, (algorithm, key));
+        }
+        verification_keys.insert(config.key_id.clone(), (algorithm, decoding_key));
+
+        Ok(Keyring {
+            key_id: config.key_id.clone(),
+            algorithm,
+            encoding_key,
+            verification_keys,
+            issuer: config.issuer.clone(),
+            audience: config.audience.clone(),
+        })
+    }
+
+    // Header of a new token, carrying the kid of the signing key
+    pub fn header(&self) -> Header {
+        let mut header = Header::new(self.algorithm);
+        header.kid = Some(self.key_id.clone());
+        header
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    pub fn audience(&self) -> &str {
+        &self.audience
+    }
+
+    // Rules a token signed with this algorithm must pass besides its signature
+    pub fn validation(&self, algorithm: Algorithm) -> Validation {
+        let mut validation = Validation::new(algorithm);
+        validation.set_required_spec_claims(&["exp", "nbf", "sub", "iss", "aud"]);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        validation.validate_nbf = true;
+        validation
+    }
+
+    pub fn encoding_key(&self) -> &EncodingKey {
+        &self.encoding_key
+    }
+
+    // Tokens without kid were issued before keys had ids and are checked against the signing key.
+    // The algorithm comes from the key, never from the token header.
+    pub fn verification_key(&self, key_id: Option<&str>) -> Option<(Algorithm, &DecodingKey)> {
+        self.verification_keys
+            .get(key_id.unwrap_or(&self.key_id))
+            .map(|(algorithm, key)| (*algorithm, key))
+    }
+}
+
+fn is_hmac(algorithm: Algorithm) -> bool {
+    matches!(algorithm, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512)
+}
+
+fn read_key(path: &str) -> Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("Failed to read key file {}", path))
+}
+
+fn encoding_key(algorithm: Algorithm, pem: &[u8]) -> Result<EncodingKey> {
+    let key = match algorithm {
+        Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512
+        | Algorithm::PS256 | Algorithm::PS384 | Algorithm::PS512 => EncodingKey::from_rsa_pem(pem)?,
+        Algorithm::ES256 | Algorithm::ES384 => EncodingKey::from_ec_pem(pem)?,
+        Algorithm::EdDSA => EncodingKey::from_ed_pem(pem)?,
+        _ => EncodingKey::from_secret(pem),
+    };
+    Ok(key)
+}
+
+fn decoding_key(algorithm: Algorithm, pem: &[u8]) -> Result<DecodingKey> {
+    let key = match algorithm {
+        Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512
+        | Algorithm::PS256 | Algorithm::PS384 | Algorithm::PS512 => DecodingKey::from_rsa_pem(pem)?,
+        Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_pem(pem)?,
+        Algorithm::EdDSA => DecodingKey::from_ed_pem(pem)?,
+        _ => DecodingKey::from_secret(pem),
+    };
+    Ok(key)
+}
+
+// Parses a kid:ALGORITHM:path entry of JWT_VERIFICATION_KEYS. Old HS* keys are files holding the secret.
+fn load_verification_key(entry: &str) -> Result<(String, Algorithm, DecodingKey)> {
+    let mut parts = entry.splitn(3, ':');
+    let (key_id, algorithm, path) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(key_id), Some(algorithm), Some(path)) => (key_id, algorithm, path),
+        _ => return Err(anyhow!("Invalid verification key '{}', expected kid:ALGORITHM:path", entry)),
+    };
+    let algorithm: Algorithm = algorithm.parse()
+        .with_context(|| format!("Invalid algorithm of verification key '{}'", key_id))?;
+
+    let key = read_key(path)?;
+    let key = if is_hmac(algorithm) {
+        DecodingKey::from_secret(String::from_utf8_lossy(&key).trim().as_bytes())
+    } else {
+        decoding_key(algorithm, &key).with_context(|| format!("Invalid verification key '{}'", key_id))?
+    };
+    Ok((key_id.to_string(), algorithm, key))
+}
\ No newline at end of file
@@ -0,0 +1,120 @@
+pub mod file;
+pub mod smtp;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use anyhow::{anyhow, Error};
+use log::{error, info};
+use super::config::MailConfig;
+use file::FileMailer;
+use smtp::SmtpMailer;
+
+pub type MailFuture<'a> = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>>;
+
+// Plain text message sent to a single recipient
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+// Delivery of outgoing emails, chosen with MAILER
+pub trait Mailer: Send + Sync {
+    fn send<'a>(&'a self, email: &'a Email) -> MailFuture<'a>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MailTransport {
+    Log,
+    File,
+    Smtp,
+}
+
+impl FromStr for MailTransport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "log" => Ok(MailTransport::Log),
+            "file" => Ok(MailTransport::File),
+            "smtp" => Ok(MailTransport::Smtp),
+            _ => Err(format!("Unknown mail transport '{}'", s)),
+        }
+    }
+}
+
+pub fn from_config(config: &MailConfig) -> Arc<dyn Mailer> {
+    match config.transport {
+        MailTransport::Log => Arc::new(FileMailer::new(None, config.from.clone())),
+        MailTransport::File => Arc::new(FileMailer::new(
+            Some(config.file.clone().expect("MAIL_FILE must be set when MAILER is file")),
+            config.from.clone(),
+        )),
+        MailTransport::Smtp => Arc::new(SmtpMailer::new(config)),
+    }
+}
+
+// Sends the email from a background task, so the response neither waits for the mail
+// server nor tells by its timing whether an email was sent. Failures are only logged.
+pub fn send_in_background(mailer: &Arc<dyn Mailer>, email: Email) {
+    let mailer = mailer.clone();
+    tokio::spawn(async move {
+        match mailer.send(&email).await {
+            Ok(()) => info!("Sent email '{}' to {}", email.subject, email.to),
+            Err(e) => error!("Error sending email '{}' to {}: {:?}", email.subject, email.to, e),
+        }
+    });
+}
+
+// Refuses line breaks in the values put into SMTP commands and headers, they would let the
+// recipient or subject inject commands or headers of their own
+pub fn check_header_values(from: &str, email: &Email) -> Result<(), Error> {
+    for (name, value) in [("From", from), ("To", email.to.as_str()), ("Subject", email.subject.as_str())] {
+        if value.contains(['\r', '\n']) {
+            return Err(anyhow!("{} of the email contains a line break", name));
+        }
+    }
+    Ok(())
+}
+
+// RFC 5322 message of the email, lines ending with CRLF
+pub fn format_message(from: &str, email: &Email) -> String {
+    let mut message = String::new();
+    message.push_str(&format!("From: {}\r\n", from));
+    message.push_str(&format!("To: {}\r\n", email.to));
+    message.push_str(&format!("Subject: {}\r\n", email.subject));
+    message.push_str(&format!("Date: {}\r\n", chrono::Utc::now().to_rfc2822()));
+    message.push_str("MIME-Version: 1.0\r\n");
+    message.push_str("Content-Type: text/plain; charset=utf-8\r\n");
+    message.push_str("Content-Transfer-Encoding: 8bit\r\n");
+    message.push_str("\r\n");
+    for line in email.body.lines() {
+        message.push_str(line);
+        message.push_str("\r\n");
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(to: &str, subject: &str) -> Email {
+        Email { to: to.to_string(), subject: subject.to_string(), body: "Hello\n.\nBye".to_string() }
+    }
+
+    #[test]
+    fn accepts_plain_values() {
+        assert!(check_header_values("API <no-reply@example.com>", &email("budi@example.com", "Verify your email")).is_ok());
+    }
+
+    #[test]
+    fn rejects_line_breaks() {
+        assert!(check_header_values("no-reply@example.com", &email("a@b.co>\r\nRSET", "Hi")).is_err());
+        assert!(check_header_values("no-reply@example.com", &email("a@b.co", "Hi\nBcc: x@y.z")).is_err());
+        assert!(check_header_values("no-reply@example.com\r", &email("a@b.co", "Hi")).is_err());
+    }
+}
@@ -0,0 +1,43 @@
+use anyhow::Context;
+use log::info;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use super::{check_header_values, format_message, Email, MailFuture, Mailer};
+
+// Appends every message to a local file, or writes it to the log when no file is given.
+// Meant for development and tests where no mail server is available.
+pub struct FileMailer {
+    path: Option<String>,
+    from: String,
+}
+
+impl FileMailer {
+    pub fn new(path: Option<String>, from: String) -> Self {
+        FileMailer { path, from }
+    }
+
+    async fn write(&self, email: &Email) -> Result<(), anyhow::Error> {
+        check_header_values(&self.from, email)?;
+        let message = format_message(&self.from, email);
+        match &self.path {
+            Some(path) => {
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await
+                    .with_context(|| format!("Failed to open mail file {}", path))?;
+                file.write_all(message.as_bytes()).await?;
+                file.write_all(b"\r\n").await?;
+            }
+            None => info!("Email to {}:\n{}", email.to, message),
+        }
+        Ok(())
+    }
+}
+
+impl Mailer for FileMailer {
+    fn send<'a>(&'a self, email: &'a Email) -> MailFuture<'a> {
+        Box::pin(self.write(email))
+    }
+}
\ No newline at end of file
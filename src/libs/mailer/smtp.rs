@@ -0,0 +1,250 @@
+use std::sync::Arc;
+use anyhow::{anyhow, Context, Error};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Duration};
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::ServerName;
+use super::{check_header_values, format_message, Email, MailFuture, Mailer};
+use crate::libs::config::MailConfig;
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Minimal SMTP client. The connection is upgraded with STARTTLS whenever the server offers
+// it, verifying its certificate against the bundled web PKI roots. AUTH PLAIN is only sent
+// over TLS, so a username requires a server offering STARTTLS. Without credentials a relay
+// on the local network without TLS, such as a sidecar MTA, is still served in plain text.
+pub struct SmtpMailer {
+    host: String,
+    port: u16,
+    username: Option<String>,
+    password: Option<String>,
+    from: String,
+    tls: TlsConnector,
+}
+
+impl SmtpMailer {
+    pub fn new(config: &MailConfig) -> Self {
+        let roots = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+        let tls = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .expect("Failed to set up TLS for SMTP")
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        SmtpMailer {
+            host: config.smtp_host.clone(),
+            port: config.smtp_port,
+            username: config.smtp_username.clone(),
+            password: config.smtp_password.clone(),
+            from: config.from.clone(),
+            tls: TlsConnector::from(Arc::new(tls)),
+        }
+    }
+
+    async fn deliver(&self, email: &Email) -> Result<(), Error> {
+        check_header_values(&self.from, email)?;
+        let stream = timeout(SMTP_TIMEOUT, TcpStream::connect((self.host.as_str(), self.port)))
+            .await
+            .context("Timed out connecting to SMTP server")?
+            .with_context(|| format!("Failed to connect to SMTP server {}:{}", self.host, self.port))?;
+        let mut stream = BufReader::new(stream);
+
+        expect_reply(&mut stream, &[220]).await?;
+        let extensions = command(&mut stream, "EHLO crud-api", &[250]).await?;
+        if !extensions.iter().any(|extension| extension.eq_ignore_ascii_case("STARTTLS")) {
+            return self.transact(&mut stream, email, false).await;
+        }
+
+        command(&mut stream, "STARTTLS", &[220]).await?;
+        // Anything sent before the handshake could be read as if it came over TLS
+        if !stream.buffer().is_empty() {
+            return Err(anyhow!("SMTP server sent data before the TLS handshake"));
+        }
+        let name = ServerName::try_from(self.host.clone())
+            .with_context(|| format!("Invalid SMTP host name {}", self.host))?;
+        let stream = timeout(SMTP_TIMEOUT, self.tls.connect(name, stream.into_inner()))
+            .await
+            .context("Timed out in the TLS handshake with the SMTP server")?
+            .context("TLS handshake with the SMTP server failed")?;
+        let mut stream = BufReader::new(stream);
+        command(&mut stream, "EHLO crud-api", &[250]).await?;
+        self.transact(&mut stream, email, true).await
+    }
+
+    // Authenticates when configured and sends the email over an open session
+    async fn transact<S>(&self, stream: &mut BufReader<S>, email: &Email, tls: bool) -> Result<(), Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        if let Some(username) = &self.username {
+            if !tls {
+                return Err(anyhow!("SMTP server {} does not offer STARTTLS, refusing to send credentials in plain text", self.host));
+            }
+            let password = self.password.as_deref().unwrap_or_default();
+            let credentials = STANDARD.encode(format!("\0{}\0{}", username, password));
+            command(stream, &format!("AUTH PLAIN {}", credentials), &[235]).await?;
+        }
+        command(stream, &format!("MAIL FROM:<{}>", address(&self.from)), &[250]).await?;
+        command(stream, &format!("RCPT TO:<{}>", email.to), &[250, 251]).await?;
+        command(stream, "DATA", &[354]).await?;
+
+        // Lines starting with a dot are doubled so they are not read as the end of data
+        let mut data = String::new();
+        for line in format_message(&self.from, email).split("\r\n") {
+            if line.starts_with('.') {
+                data.push('.');
+            }
+            data.push_str(line);
+            data.push_str("\r\n");
+        }
+        data.push_str(".\r\n");
+        stream.get_mut().write_all(data.as_bytes()).await?;
+        stream.get_mut().flush().await?;
+        expect_reply(stream, &[250]).await?;
+
+        command(stream, "QUIT", &[221]).await?;
+        Ok(())
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send<'a>(&'a self, email: &'a Email) -> MailFuture<'a> {
+        Box::pin(self.deliver(email))
+    }
+}
+
+// Bare address of a "Name <address>" sender
+fn address(from: &str) -> &str {
+    match (from.find('<'), from.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &from[start + 1..end],
+        _ => from,
+    }
+}
+
+async fn command<S>(stream: &mut BufReader<S>, line: &str, expected: &[u16]) -> Result<Vec<String>, Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream.get_mut().write_all(format!("{}\r\n", line).as_bytes()).await?;
+    stream.get_mut().flush().await?;
+    expect_reply(stream, expected).await
+}
+
+// Reads a possibly multiline reply, whose last line has a space after the code. Returns the
+// text of its lines, which for EHLO are the extensions of the server.
+async fn expect_reply<S>(stream: &mut BufReader<S>, expected: &[u16]) -> Result<Vec<String>, Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        let read = timeout(SMTP_TIMEOUT, stream.read_line(&mut line))
+            .await
+            .context("Timed out waiting for SMTP reply")??;
+        if read == 0 {
+            return Err(anyhow!("SMTP server closed the connection"));
+        }
+        let code: u16 = line.get(..3).and_then(|code| code.parse().ok())
+            .ok_or_else(|| anyhow!("Invalid SMTP reply '{}'", line.trim_end()))?;
+        lines.push(line.get(4..).unwrap_or_default().trim_end().to_string());
+        if line.as_bytes().get(3) == Some(&b'-') {
+            continue;
+        }
+        if !expected.contains(&code) {
+            return Err(anyhow!("Unexpected SMTP reply '{}'", line.trim_end()));
+        }
+        return Ok(lines);
+    }
+}
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+    use super::*;
+    use crate::libs::mailer::MailTransport;
+
+    // SMTP server on a local port answering each command with the next reply, returns the
+    // commands it received until the client went away
+    async fn server(replies: &'static [&'static str]) -> (u16, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            stream.get_mut().write_all(b"220 test\r\n").await.unwrap();
+            let mut replies = replies.iter();
+            let mut received = Vec::new();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                    return received;
+                }
+                if in_data {
+                    in_data = line != ".\r\n";
+                    if in_data {
+                        continue;
+                    }
+                } else {
+                    received.push(line.trim_end().to_string());
+                }
+                if let Some(reply) = replies.next() {
+                    in_data = reply.starts_with("354");
+                    stream.get_mut().write_all(format!("{}\r\n", reply).as_bytes()).await.unwrap();
+                }
+            }
+        });
+        (port, handle)
+    }
+
+    fn mailer(port: u16, username: Option<&str>) -> SmtpMailer {
+        SmtpMailer::new(&MailConfig {
+            transport: MailTransport::Smtp,
+            file: None,
+            from: "API <no-reply@example.com>".to_string(),
+            smtp_host: "127.0.0.1".to_string(),
+            smtp_port: port,
+            smtp_username: username.map(str::to_string),
+            smtp_password: username.map(|_| "secret".to_string()),
+            base_url: "http://localhost:8080".to_string(),
+        })
+    }
+
+    fn email() -> Email {
+        Email { to: "budi@example.com".to_string(), subject: "Hi".to_string(), body: "Hello".to_string() }
+    }
+
+    #[tokio::test]
+    async fn delivers_in_plain_text_without_credentials() {
+        let (port, server) = server(&["250 test", "250 ok", "250 ok", "354 go", "250 queued", "221 bye"]).await;
+        mailer(port, None).deliver(&email()).await.unwrap();
+        assert_eq!(server.await.unwrap(), [
+            "EHLO crud-api",
+            "MAIL FROM:<no-reply@example.com>",
+            "RCPT TO:<budi@example.com>",
+            "DATA",
+            "QUIT",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn refuses_to_authenticate_without_starttls() {
+        let (port, server) = server(&["250-test\r\n250 AUTH PLAIN"]).await;
+        let error = mailer(port, Some("api")).deliver(&email()).await.unwrap_err();
+        assert!(error.to_string().contains("does not offer STARTTLS"), "{}", error);
+        assert_eq!(server.await.unwrap(), ["EHLO crud-api"]);
+    }
+
+    #[tokio::test]
+    async fn rejects_replies_sent_ahead_of_the_tls_handshake() {
+        let (port, server) = server(&["250-test\r\n250 STARTTLS", "220 go\r\n250 injected"]).await;
+        let error = mailer(port, Some("api")).deliver(&email()).await.unwrap_err();
+        assert!(error.to_string().contains("before the TLS handshake"), "{}", error);
+        assert_eq!(server.await.unwrap(), ["EHLO crud-api", "STARTTLS"]);
+    }
+}
@@ -0,0 +1,74 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use super::request::Request;
+use super::validation::ValidationErrors;
+
+pub const DEFAULT_LIMIT: i64 = 20;
+pub const MAX_LIMIT: i64 = 100;
+
+// limit/offset or keyset cursor requested through the query string
+#[derive(Debug)]
+pub struct PageRequest {
+    pub limit: i64,
+    pub offset: i64,
+    pub cursor: Option<String>,
+}
+
+impl PageRequest {
+    pub fn from_request(request: &Request, errors: &mut ValidationErrors) -> Self {
+        let limit = match request.query.get("limit") {
+            Some(limit) => match limit.parse::<i64>() {
+                Ok(limit) if (1..=MAX_LIMIT).contains(&limit) => limit,
+                _ => {
+                    errors.add("limit", &format!("limit must be a number between 1 and {}", MAX_LIMIT));
+                    DEFAULT_LIMIT
+                }
+            },
+            None => DEFAULT_LIMIT,
+        };
+
+        let offset = match request.query.get("offset") {
+            Some(offset) => match offset.parse::<i64>() {
+                Ok(offset) if offset >= 0 => offset,
+                _ => {
+                    errors.add("offset", "offset must be a positive number");
+                    0
+                }
+            },
+            None => 0,
+        };
+
+        let cursor = request.query.get("cursor").filter(|c| !c.is_empty()).cloned();
+        if cursor.is_some() && offset > 0 {
+            errors.add("cursor", "cursor cannot be combined with offset");
+        }
+
+        PageRequest { limit, offset, cursor }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Pagination {
+    pub limit: i64,
+    pub offset: i64,
+    pub total: i64,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Page<T> {
+    pub data: Vec<T>,
+    pub pagination: Pagination,
+}
+
+// Cursors are opaque to clients: URL-safe base64 of the JSON encoded position
+pub fn encode_cursor<T: Serialize>(position: &T) -> String {
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(position).unwrap_or_default())
+}
+
+pub fn decode_cursor<T: DeserializeOwned>(cursor: &str) -> Option<T> {
+    let bytes = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
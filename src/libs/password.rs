@@ -0,0 +1,142 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use super::config::PasswordConfig;
+
+// Password rules applied wherever a password is set
+#[derive(Debug)]
+pub struct PasswordPolicy {
+    min_length: usize,
+    max_length: usize,
+    require_uppercase: bool,
+    require_lowercase: bool,
+    require_digit: bool,
+    require_special: bool,
+    banned: HashSet<String>,
+}
+
+impl PasswordPolicy {
+    pub fn from_config(config: &PasswordConfig) -> io::Result<Self> {
+        let banned = match &config.banned_list_file {
+            Some(path) => fs::read_to_string(path)?
+                .lines()
+                .map(|line| line.trim().to_lowercase())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .collect(),
+            None => HashSet::new(),
+        };
+
+        Ok(PasswordPolicy {
+            min_length: config.min_length,
+            max_length: config.max_length,
+            require_uppercase: config.require_uppercase,
+            require_lowercase: config.require_lowercase,
+            require_digit: config.require_digit,
+            require_special: config.require_special,
+            banned,
+        })
+    }
+
+    // Returns every rule the password breaks, empty when it is acceptable
+    pub fn check(&self, password: &str, name: &str, email: &str) -> Vec<String> {
+        let mut violations = Vec::new();
+
+        if password.chars().count() < self.min_length {
+            violations.push(format!("Password must be at least {} characters long", self.min_length));
+        }
+        // Counted in bytes, bcrypt ignores everything past its first 72
+        if password.len() > self.max_length {
+            violations.push(format!("Password must be at most {} bytes long", self.max_length));
+        }
+
+        if self.require_uppercase && !password.chars().any(|c| c.is_uppercase()) {
+            violations.push("Password must contain an uppercase letter".to_string());
+        }
+        if self.require_lowercase && !password.chars().any(|c| c.is_lowercase()) {
+            violations.push("Password must contain a lowercase letter".to_string());
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            violations.push("Password must contain a digit".to_string());
+        }
+        if self.require_special && !password.chars().any(|c| !c.is_alphanumeric()) {
+            violations.push("Password must contain a special character".to_string());
+        }
+
+        let lowercase = password.to_lowercase();
+        if self.banned.contains(&lowercase) {
+            violations.push("Password is too common".to_string());
+        }
+
+        // Very short names would reject too many passwords, so only names of 3+ characters count
+        let name = name.trim().to_lowercase();
+        if name.chars().count() >= 3 && lowercase.contains(&name) {
+            violations.push("Password must not contain your name".to_string());
+        }
+        let email = email.trim().to_lowercase();
+        let local_part = email.split('@').next().unwrap_or_default();
+        if !email.is_empty() && (lowercase.contains(&email) || (local_part.chars().count() >= 3 && lowercase.contains(local_part))) {
+            violations.push("Password must not contain your email".to_string());
+        }
+
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 10,
+            max_length: 72,
+            require_uppercase: true,
+            require_lowercase: true,
+            require_digit: true,
+            require_special: true,
+            banned: HashSet::from(["password123!a".to_string()]),
+        }
+    }
+
+    fn check(password: &str) -> Vec<String> {
+        policy().check(password, "Santoso", "budi@example.com")
+    }
+
+    #[test]
+    fn accepts_strong_password() {
+        assert!(check("Correct4Horse!").is_empty());
+    }
+
+    #[test]
+    fn reports_every_missing_class() {
+        assert_eq!(check("aaaaaaaaaa").len(), 3);
+        assert_eq!(check("Short1!"), vec!["Password must be at least 10 characters long"]);
+    }
+
+    #[test]
+    fn max_length_counts_bytes() {
+        // 40 characters but 80 bytes, bcrypt would silently drop the end
+        let password = format!("Aa1!{}", "é".repeat(38));
+        assert_eq!(password.chars().count(), 42);
+        assert_eq!(check(&password), vec!["Password must be at most 72 bytes long"]);
+        assert!(check(&format!("Aa1!{}", "x".repeat(68))).is_empty());
+    }
+
+    #[test]
+    fn rejects_banned_passwords_ignoring_case() {
+        assert_eq!(check("PASSWORD123!a"), vec!["Password is too common"]);
+    }
+
+    #[test]
+    fn rejects_name_and_email() {
+        assert_eq!(check("Santoso2024!x"), vec!["Password must not contain your name"]);
+        assert_eq!(check("Xbudi2024!xy"), vec!["Password must not contain your email"]);
+    }
+
+    #[test]
+    fn loads_shipped_banned_list() {
+        let config = crate::libs::config::Config::from_env().password;
+        let policy = PasswordPolicy::from_config(&config).unwrap();
+        assert!(policy.banned.contains("password"));
+    }
+}
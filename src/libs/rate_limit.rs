@@ -0,0 +1,468 @@
+pub mod memory;
+pub mod postgres;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, Ipv6Addr};
+use std::num::NonZeroU32;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::time::Duration;
+use anyhow::Error;
+use deadpool_postgres::Pool;
+use log::error;
+use super::config::RateLimitConfig;
+use super::request::Request;
+use super::response::Response;
+use super::router::{Limiter, Route};
+use super::token::hash_opaque_token;
+use memory::MemoryStore;
+use postgres::PostgresStore;
+
+const API_KEY_HEADER: &str = "X-API-Key";
+
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+// What a quota is counted per. Callers without a user id or API key are counted per IP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyKind {
+    Ip,
+    User,
+    ApiKey,
+}
+
+impl FromStr for KeyKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ip" => Ok(KeyKind::Ip),
+            "user" => Ok(KeyKind::User),
+            "api_key" => Ok(KeyKind::ApiKey),
+            _ => Err(format!("Unknown rate limit key '{}'", s)),
+        }
+    }
+}
+
+// Quota written as <count>/<s|m|h>, optionally followed by @ip, @user or @api_key (ip by default)
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitRule {
+    pub count: NonZeroU32,
+    pub period: Duration,
+    pub key: KeyKind,
+}
+
+impl RateLimitRule {
+    // Time for one request of the quota to be replenished
+    pub fn interval(&self) -> Duration {
+        self.period / self.count.get()
+    }
+}
+
+impl FromStr for RateLimitRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (quota, key) = match s.split_once('@') {
+            Some((quota, key)) => (quota, key.parse()?),
+            None => (s, KeyKind::Ip),
+        };
+        let (count, unit) = quota.split_once('/').ok_or_else(|| format!("Invalid rate limit '{}'", s))?;
+        let count = count.trim().parse::<NonZeroU32>().map_err(|_| format!("Invalid rate limit count in '{}'", s))?;
+        let period = match unit.trim() {
+            "s" => Duration::from_secs(1),
+            "m" => Duration::from_secs(60),
+            "h" => Duration::from_secs(60 * 60),
+            _ => return Err(format!("Invalid rate limit period in '{}', expected s, m or h", s)),
+        };
+        Ok(RateLimitRule { count, period, key })
+    }
+}
+
+// Where the request counters live, chosen with RATE_LIMIT_STORE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitBackend {
+    Memory,
+    Postgres,
+}
+
+impl FromStr for RateLimitBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "memory" => Ok(RateLimitBackend::Memory),
+            "postgres" => Ok(RateLimitBackend::Postgres),
+            _ => Err(format!("Unknown rate limit store '{}'", s)),
+        }
+    }
+}
+
+// One quota, shared by the routes of a Limiter class or owned by a single route
+#[derive(Debug, Clone)]
+pub struct Bucket {
+    pub name: String,
+    pub rule: RateLimitRule,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ClientKey {
+    Ip(IpAddr),
+    User(i32),
+    // SHA-256 of the key, so the keys themselves are not kept in memory
+    ApiKey(String),
+    // Shared by clients that cannot be told apart
+    Overflow,
+}
+
+impl fmt::Display for ClientKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientKey::Ip(ip) => write!(f, "ip:{}", ip),
+            ClientKey::User(id) => write!(f, "user:{}", id),
+            ClientKey::ApiKey(hash) => write!(f, "api_key:{}", hash),
+            ClientKey::Overflow => f.write_str("overflow"),
+        }
+    }
+}
+
+// Quota left to the caller of a request, sent back in the RateLimit headers
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitStatus {
+    pub limit: u32,
+    pub remaining: u32,
+    // Until the quota is full again
+    pub reset: Duration,
+    // Set when the request is over the quota, until it would be let through
+    pub retry_after: Option<Duration>,
+}
+
+impl RateLimitStatus {
+    pub fn is_limited(&self) -> bool {
+        self.retry_after.is_some()
+    }
+
+    pub fn apply(&self, mut response: Response) -> Response {
+        response.set_header("RateLimit-Limit", self.limit.to_string());
+        response.set_header("RateLimit-Remaining", self.remaining.to_string());
+        response.set_header("RateLimit-Reset", ceil_secs(self.reset).to_string());
+        if let Some(retry_after) = self.retry_after {
+            response.set_header("Retry-After", ceil_secs(retry_after).max(1).to_string());
+        }
+        response
+    }
+}
+
+// Status of a GCRA quota of limit requests, one replenished every interval, whose
+// theoretical arrival time is tat seconds from now. Shared by the stores, so the headers
+// agree whichever one counts.
+pub fn gcra_status(limit: u32, interval: Duration, tat: f64, limited: bool) -> RateLimitStatus {
+    let interval = interval.as_secs_f64();
+    let burst = interval * f64::from(limit);
+    if limited {
+        return RateLimitStatus {
+            limit,
+            remaining: 0,
+            reset: seconds(tat),
+            // The request would fit once tat has moved back within the burst
+            retry_after: Some(seconds(tat + interval - burst)),
+        };
+    }
+    RateLimitStatus {
+        limit,
+        remaining: (((burst - tat) / interval).floor().max(0.0) as u32).min(limit),
+        reset: seconds(tat),
+        retry_after: None,
+    }
+}
+
+// Counters of the buckets. Both stores implement GCRA, so a quota behaves the same whichever is used.
+pub trait RateLimitStore: Send + Sync {
+    // Counts one request of the client against the bucket
+    fn check<'a>(&'a self, bucket: &'a Bucket, key: &'a ClientKey) -> StoreFuture<'a, Result<RateLimitStatus, Error>>;
+    // Forgets clients whose quota has fully refilled, they would start from a full one anyway
+    fn evict_idle(&self) -> StoreFuture<'_, ()>;
+}
+
+// Picks the bucket of each route and the client key of each request, counting is left to the store
+pub struct RateLimits {
+    buckets: Vec<Bucket>,
+    classes: HashMap<Limiter, usize>,
+    // Keyed by "METHOD /template"
+    routes: HashMap<String, usize>,
+    // SHA-256 of the known API keys
+    api_keys: HashSet<String>,
+    store: Box<dyn RateLimitStore>,
+    sweep_interval: Duration,
+}
+
+impl RateLimits {
+    pub fn from_config(config: &RateLimitConfig, pool: &Pool) -> Self {
+        let mut buckets = vec![
+            Bucket { name: "common".to_string(), rule: config.common },
+            Bucket { name: "hard".to_string(), rule: config.hard },
+            Bucket { name: "register".to_string(), rule: config.register },
+        ];
+        let classes = HashMap::from([(Limiter::Common, 0), (Limiter::Hard, 1), (Limiter::Register, 2)]);
+        let mut routes = HashMap::new();
+        for (route, rule) in &config.routes {
+            routes.insert(route.clone(), buckets.len());
+            buckets.push(Bucket { name: route.clone(), rule: *rule });
+        }
+
+        let store: Box<dyn RateLimitStore> = match config.store {
+            RateLimitBackend::Memory => Box::new(MemoryStore::new(&buckets, config.max_keys)),
+            RateLimitBackend::Postgres => Box::new(PostgresStore::new(pool.clone())),
+        };
+        RateLimits {
+            buckets,
+            classes,
+            routes,
+            api_keys: config.api_keys.iter().map(|key| hash_opaque_token(key)).collect(),
+            store,
+            sweep_interval: Duration::from_secs(config.sweep_interval.max(1)),
+        }
+    }
+
+    // Counts the request against the quota of its caller on the route, None when the route
+    // has no quota. A quota set for the route itself replaces the one of its Limiter class,
+    // and requests matching no route count against the common quota.
+    // When the store fails the request is let through, the limits are not worth an outage.
+    pub async fn check<S>(&self, route: Option<&Route<S>>, request: &Request) -> Option<RateLimitStatus> {
+        let index = match route {
+            Some(route) => match self.routes.get(&format!("{} {}", route.method, route.template)) {
+                Some(index) => *index,
+                None => *self.classes.get(&route.limiter?)?,
+            },
+            None => *self.classes.get(&Limiter::Common)?,
+        };
+        let bucket = &self.buckets[index];
+        let key = self.client_key(bucket.rule.key, request);
+
+        match self.store.check(bucket, &key).await {
+            Ok(status) => Some(status),
+            Err(e) => {
+                error!("Error checking rate limit {} of {}: {:?}", bucket.name, key, e);
+                None
+            }
+        }
+    }
+
+    // Background task evicting idle clients every RATE_LIMIT_SWEEP_INTERVAL seconds
+    pub async fn run_eviction(&self) {
+        let mut ticker = tokio::time::interval(self.sweep_interval);
+        loop {
+            ticker.tick().await;
+            self.store.evict_idle().await;
+        }
+    }
+
+    // An API key only gets its own quota once it is known, otherwise a client sending a new
+    // random key with every request would get a fresh quota each time
+    fn client_key(&self, kind: KeyKind, request: &Request) -> ClientKey {
+        match kind {
+            KeyKind::User => {
+                if let Some(auth) = &request.auth {
+                    return ClientKey::User(auth.user_id);
+                }
+            }
+            KeyKind::ApiKey => {
+                if let Some(api_key) = request.header(API_KEY_HEADER).filter(|key| !key.is_empty()) {
+                    let hash = hash_opaque_token(api_key);
+                    if self.api_keys.contains(&hash) {
+                        return ClientKey::ApiKey(hash);
+                    }
+                }
+            }
+            KeyKind::Ip => {}
+        }
+        match request.remote_addr {
+            Some(addr) => ClientKey::Ip(ip_key(addr.ip())),
+            None => ClientKey::Overflow,
+        }
+    }
+}
+
+fn seconds(value: f64) -> Duration {
+    Duration::from_secs_f64(value.max(0.0))
+}
+
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+// IPv6 clients usually get a whole /64, so it counts as one address
+fn ip_key(ip: IpAddr) -> IpAddr {
+    match ip.to_canonical() {
+        IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(u128::from(ip) & (u128::MAX << 64))),
+        ip => ip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket(rule: &str) -> Bucket {
+        Bucket { name: "test".to_string(), rule: rule.parse().unwrap() }
+    }
+
+    #[test]
+    fn parses_rules() {
+        let rule: RateLimitRule = "100/s@user".parse().unwrap();
+        assert_eq!((rule.count.get(), rule.period, rule.key), (100, Duration::from_secs(1), KeyKind::User));
+        let rule: RateLimitRule = "5/m".parse().unwrap();
+        assert_eq!((rule.count.get(), rule.period, rule.key), (5, Duration::from_secs(60), KeyKind::Ip));
+        let rule: RateLimitRule = "2/h@api_key".parse().unwrap();
+        assert_eq!((rule.period, rule.key), (Duration::from_secs(3600), KeyKind::ApiKey));
+        assert_eq!(rule.interval(), Duration::from_secs(1800));
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for rule in ["", "5", "0/s", "-1/s", "5/d", "5/s@email", "x/m"] {
+            assert!(rule.parse::<RateLimitRule>().is_err(), "{} should be rejected", rule);
+        }
+    }
+
+    #[test]
+    fn ipv6_is_keyed_by_its_64_prefix() {
+        let ip: IpAddr = "2001:db8:1:2:aaaa:bbbb:cccc:dddd".parse().unwrap();
+        assert_eq!(ip_key(ip), "2001:db8:1:2::".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn ipv4_and_mapped_ipv4_keep_the_address() {
+        let ip: IpAddr = "192.0.2.7".parse().unwrap();
+        assert_eq!(ip_key(ip), ip);
+        assert_eq!(ip_key("::ffff:192.0.2.7".parse().unwrap()), ip);
+    }
+
+    #[tokio::test]
+    async fn clients_over_max_keys_share_one_quota() {
+        let bucket = bucket("1/m");
+        let store = MemoryStore::new(std::slice::from_ref(&bucket), 1);
+        let client = |n: u8| ClientKey::Ip(IpAddr::from([192, 0, 2, n]));
+
+        assert!(!store.check(&bucket, &client(1)).await.unwrap().is_limited());
+        // The limiter is full, the next two clients count against the overflow key together
+        assert!(!store.check(&bucket, &client(2)).await.unwrap().is_limited());
+        assert!(store.check(&bucket, &client(3)).await.unwrap().is_limited());
+    }
+
+    #[tokio::test]
+    async fn tracked_client_keeps_its_quota_when_limiter_is_full() {
+        let bucket = bucket("2/m");
+        let store = MemoryStore::new(std::slice::from_ref(&bucket), 1);
+        let client = |n: u8| ClientKey::Ip(IpAddr::from([192, 0, 2, n]));
+
+        assert!(!store.check(&bucket, &client(1)).await.unwrap().is_limited());
+        // New clients use up the overflow quota
+        for n in 2..5 {
+            store.check(&bucket, &client(n)).await.unwrap();
+        }
+        assert!(store.check(&bucket, &client(5)).await.unwrap().is_limited());
+
+        let status = store.check(&bucket, &client(1)).await.unwrap();
+        assert_eq!((status.is_limited(), status.remaining), (false, 0));
+    }
+
+    // The arithmetic of the PostgresStore statement, which postgres_store_matches_memory_store
+    // runs for real: tat moves one interval on when it stays within the burst, otherwise it
+    // is left alone and the request is limited
+    fn reference_gcra(stored: &mut Option<f64>, now: f64, limit: u32, interval: Duration) -> RateLimitStatus {
+        let burst = interval.as_secs_f64() * f64::from(limit);
+        let tat = stored.unwrap_or(now).max(now) + interval.as_secs_f64();
+        if tat <= now + burst {
+            *stored = Some(tat);
+            gcra_status(limit, interval, tat - now, false)
+        } else {
+            gcra_status(limit, interval, stored.unwrap() - now, true)
+        }
+    }
+
+    fn close(a: Duration, b: Duration) -> bool {
+        (a.as_secs_f64() - b.as_secs_f64()).abs() < 0.1
+    }
+
+    fn assert_same_status(a: &RateLimitStatus, b: &RateLimitStatus) {
+        assert_eq!((a.remaining, a.is_limited()), (b.remaining, b.is_limited()));
+        assert!(close(a.reset, b.reset), "{:?} != {:?}", a.reset, b.reset);
+        match (a.retry_after, b.retry_after) {
+            (Some(a), Some(b)) => assert!(close(a, b), "{:?} != {:?}", a, b),
+            (a, b) => assert_eq!(a.is_some(), b.is_some()),
+        }
+    }
+
+    #[tokio::test]
+    async fn memory_store_matches_reference_gcra() {
+        let bucket = bucket("3/m");
+        let store = MemoryStore::new(std::slice::from_ref(&bucket), 10);
+        let key = ClientKey::User(1);
+        let mut stored = None;
+        for _ in 0..5 {
+            let memory = store.check(&bucket, &key).await.unwrap();
+            let reference = reference_gcra(&mut stored, 0.0, 3, bucket.rule.interval());
+            assert_same_status(&memory, &reference);
+        }
+    }
+
+    // Needs DATABASE_URL pointing at a database with migrations/010_rate_limits.sql applied
+    #[tokio::test]
+    #[ignore = "needs a Postgres database in DATABASE_URL"]
+    async fn postgres_store_matches_memory_store() {
+        use std::str::FromStr;
+        use deadpool_postgres::Manager;
+        use tokio_postgres::{Config, NoTls};
+
+        let config = Config::from_str(&crate::libs::get_db_url()).expect("Failed to parse DATABASE_URL");
+        let pool = Pool::new(Manager::new(config, NoTls), 1);
+        let bucket = Bucket {
+            name: format!("test-{}", std::process::id()),
+            rule: "3/m".parse().unwrap(),
+        };
+        let postgres = PostgresStore::new(pool.clone());
+        let memory = MemoryStore::new(std::slice::from_ref(&bucket), 10);
+        let key = ClientKey::User(1);
+
+        let mut statuses = Vec::new();
+        for _ in 0..5 {
+            statuses.push((postgres.check(&bucket, &key).await, memory.check(&bucket, &key).await.unwrap()));
+        }
+        let db = pool.get().await.unwrap();
+        db.execute("DELETE FROM rate_limits WHERE bucket = $1", &[&bucket.name]).await.unwrap();
+
+        for (postgres, memory) in statuses {
+            assert_same_status(&postgres.unwrap(), &memory);
+        }
+    }
+
+    #[test]
+    fn quota_refills_one_interval_at_a_time() {
+        // 2 per 10 seconds, one request back every 5
+        let interval = Duration::from_secs(5);
+        let mut stored = None;
+        let first = reference_gcra(&mut stored, 0.0, 2, interval);
+        assert_eq!((first.remaining, first.reset), (1, Duration::from_secs(5)));
+        let second = reference_gcra(&mut stored, 0.0, 2, interval);
+        assert_eq!((second.remaining, second.reset), (0, Duration::from_secs(10)));
+
+        let limited = reference_gcra(&mut stored, 1.0, 2, interval);
+        assert_eq!(limited.retry_after, Some(Duration::from_secs(4)));
+        assert_eq!(limited.reset, Duration::from_secs(9));
+        assert_eq!(stored, Some(10.0));
+
+        let refilled = reference_gcra(&mut stored, 5.0, 2, interval);
+        assert!(!refilled.is_limited());
+        assert_eq!(refilled.remaining, 0);
+        let idle = reference_gcra(&mut stored, 100.0, 2, interval);
+        assert_eq!(idle.remaining, 1);
+    }
+
+    #[test]
+    fn missing_snapshot_row_counts_as_a_full_burst() {
+        let status = gcra_status(10, Duration::from_secs(6), 60.0, true);
+        assert_eq!((status.remaining, status.reset, status.retry_after), (0, Duration::from_secs(60), Some(Duration::from_secs(6))));
+    }
+}
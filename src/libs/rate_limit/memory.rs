@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use anyhow::{anyhow, Error};
+use governor::{Quota, RateLimiter};
+use governor::clock::{Clock, DefaultClock};
+use governor::middleware::StateInformationMiddleware;
+use governor::nanos::Nanos;
+use governor::state::StateStore;
+use governor::state::keyed::{DashMapStateStore, ShrinkableKeyedStateStore};
+use log::{debug, warn};
+use super::{gcra_status, Bucket, ClientKey, RateLimitStatus, RateLimitStore, StoreFuture};
+
+type ClientLimiter = RateLimiter<ClientKey, ClientStates, DefaultClock, StateInformationMiddleware>;
+
+// State of the clients of one limiter. Governor owns its store and has no lookup by key,
+// so the map is shared to tell tracked clients from new ones.
+#[derive(Clone, Default)]
+struct ClientStates(Arc<DashMapStateStore<ClientKey>>);
+
+impl StateStore for ClientStates {
+    type Key = ClientKey;
+
+    fn measure_and_replace<T, F, E>(&self, key: &ClientKey, f: F) -> Result<T, E>
+    where
+        F: Fn(Option<Nanos>) -> Result<(T, Nanos), E>,
+    {
+        self.0.measure_and_replace(key, f)
+    }
+}
+
+impl ShrinkableKeyedStateStore<ClientKey> for ClientStates {
+    fn retain_recent(&self, drop_below: Nanos) {
+        self.0.retain_recent(drop_below);
+    }
+
+    fn shrink_to_fit(&self) {
+        ShrinkableKeyedStateStore::shrink_to_fit(&*self.0);
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+// Governor limiters inside this process. Each replica counts on its own, so the limits
+// effectively grow with the number of replicas.
+pub struct MemoryStore {
+    limiters: HashMap<String, (ClientLimiter, ClientStates)>,
+    // Clients tracked by one limiter before new ones share a single quota
+    max_keys: usize,
+    // Shared by every limiter, so their instants can be compared with now
+    clock: DefaultClock,
+}
+
+impl MemoryStore {
+    pub fn new(buckets: &[Bucket], max_keys: usize) -> Self {
+        let clock = DefaultClock::default();
+        let limiters = buckets.iter().map(|bucket| {
+            let quota = Quota::with_period(bucket.rule.interval())
+                .expect("Rate limit period is too short for its count")
+                .allow_burst(bucket.rule.count);
+            let states = ClientStates::default();
+            (bucket.name.clone(), (ClientLimiter::new(quota, states.clone(), &clock), states))
+        }).collect();
+        MemoryStore { limiters, max_keys, clock }
+    }
+
+    fn count(&self, bucket: &Bucket, key: &ClientKey) -> Result<RateLimitStatus, Error> {
+        let (limiter, states) = self.limiters.get(&bucket.name).ok_or_else(|| anyhow!("Unknown rate limit bucket {}", bucket.name))?;
+
+        // Idle clients are only evicted by evict_idle, scanning the limiter here would make
+        // every request pay for a full one once an attacker has filled it. Clients already
+        // tracked keep their own quota, so a flood of new keys cannot use up theirs.
+        let mut key = key;
+        if limiter.len() >= self.max_keys && !states.0.contains_key(key) {
+            warn!("Rate limiter {} tracks {} clients, counting new ones together", bucket.name, limiter.len());
+            key = &ClientKey::Overflow;
+        }
+
+        // Governor does not expose its tat, it is worked back from what it reports
+        let limit = bucket.rule.count.get();
+        let interval = bucket.rule.interval();
+        match limiter.check_key(key) {
+            Ok(snapshot) => {
+                let tat = interval * (limit - snapshot.remaining_burst_capacity());
+                Ok(gcra_status(limit, interval, tat.as_secs_f64(), false))
+            }
+            Err(not_until) => {
+                let wait = not_until.wait_time_from(self.clock.now());
+                let tat = wait + interval * (limit - 1);
+                Ok(gcra_status(limit, interval, tat.as_secs_f64(), true))
+            }
+        }
+    }
+}
+
+impl RateLimitStore for MemoryStore {
+    fn check<'a>(&'a self, bucket: &'a Bucket, key: &'a ClientKey) -> StoreFuture<'a, Result<RateLimitStatus, Error>> {
+        Box::pin(async move { self.count(bucket, key) })
+    }
+
+    fn evict_idle(&self) -> StoreFuture<'_, ()> {
+        Box::pin(async move {
+            for (limiter, _) in self.limiters.values() {
+                limiter.retain_recent();
+                limiter.shrink_to_fit();
+            }
+            let tracked: usize = self.limiters.values().map(|(limiter, _)| limiter.len()).sum();
+            debug!("Rate limiters track {} clients", tracked);
+        })
+    }
+}
\ No newline at end of file
@@ -0,0 +1,76 @@
+use anyhow::Error;
+use deadpool_postgres::Pool;
+use log::{debug, error};
+use super::{gcra_status, Bucket, ClientKey, RateLimitStatus, RateLimitStore, StoreFuture};
+
+// GCRA in one statement: the new tat is stored only when it stays within the burst,
+// otherwise the row is left alone and the current tat tells when to retry. The clock of
+// the database is used, so replicas with drifting clocks still agree.
+const CHECK: &str = "\
+    WITH clock AS (SELECT extract(epoch FROM clock_timestamp())::float8 AS now), \
+    counted AS ( \
+        INSERT INTO rate_limits AS r (bucket, key, tat) \
+        SELECT $1, $2, clock.now + $3 FROM clock \
+        ON CONFLICT (bucket, key) DO UPDATE \
+            SET tat = GREATEST(r.tat, EXCLUDED.tat - $3) + $3 \
+            WHERE GREATEST(r.tat, EXCLUDED.tat - $3) + $3 <= EXCLUDED.tat - $3 + $4 \
+        RETURNING r.tat \
+    ) \
+    SELECT clock.now, (SELECT tat FROM counted), \
+        (SELECT tat FROM rate_limits WHERE bucket = $1 AND key = $2) \
+    FROM clock";
+
+// Counters in the rate_limits table, so every replica counts against the same quota. Each
+// rate limited request takes a connection from the pool the handlers use, and when the
+// database fails the request is let through (see RateLimits::check).
+pub struct PostgresStore {
+    pool: Pool,
+}
+
+impl PostgresStore {
+    pub fn new(pool: Pool) -> Self {
+        PostgresStore { pool }
+    }
+
+    async fn count(&self, bucket: &Bucket, key: &ClientKey) -> Result<RateLimitStatus, Error> {
+        let db = self.pool.get().await?;
+        let limit = bucket.rule.count.get();
+        let interval = bucket.rule.interval().as_secs_f64();
+        let burst = interval * f64::from(limit);
+
+        let row = db.query_one(CHECK, &[&bucket.name, &key.to_string(), &interval, &burst]).await?;
+        let now: f64 = row.get(0);
+        let status = match row.get::<_, Option<f64>>(1) {
+            Some(tat) => gcra_status(limit, bucket.rule.interval(), tat - now, false),
+            None => {
+                // The row is missing from the snapshot when a concurrent request inserted it,
+                // that request used the whole burst at worst
+                let tat = row.get::<_, Option<f64>>(2).unwrap_or(now + burst);
+                gcra_status(limit, bucket.rule.interval(), tat - now, true)
+            }
+        };
+        Ok(status)
+    }
+}
+
+impl RateLimitStore for PostgresStore {
+    fn check<'a>(&'a self, bucket: &'a Bucket, key: &'a ClientKey) -> StoreFuture<'a, Result<RateLimitStatus, Error>> {
+        Box::pin(self.count(bucket, key))
+    }
+
+    fn evict_idle(&self) -> StoreFuture<'_, ()> {
+        Box::pin(async move {
+            let db = match self.pool.get().await {
+                Ok(client) => client,
+                Err(e) => {
+                    error!("Failed to get a database connection from the pool: {:?}", e);
+                    return;
+                }
+            };
+            match db.execute("DELETE FROM rate_limits WHERE tat < extract(epoch FROM now())", &[]).await {
+                Ok(deleted) => debug!("Swept {} idle rate limit counters", deleted),
+                Err(e) => error!("Error deleting idle rate limit counters: {:?}", e),
+            }
+        })
+    }
+}
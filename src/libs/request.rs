@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+use super::token::AuthContext;
+
+pub const MAX_HEADER_SIZE: usize = 8 * 1024;
+pub const MAX_HEADERS: usize = 100;
+pub const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Patch,
+    Delete,
+    Options,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Patch => "PATCH",
+            Method::Delete => "DELETE",
+            Method::Options => "OPTIONS",
+        }
+    }
+}
+
+impl FromStr for Method {
+    type Err = RequestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "GET" => Ok(Method::Get),
+            "HEAD" => Ok(Method::Head),
+            "POST" => Ok(Method::Post),
+            "PUT" => Ok(Method::Put),
+            "PATCH" => Ok(Method::Patch),
+            "DELETE" => Ok(Method::Delete),
+            "OPTIONS" => Ok(Method::Options),
+            _ => Err(RequestError::NotImplemented(format!("Unsupported method {}", s))),
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Version::Http10 => f.write_str("HTTP/1.0"),
+            Version::Http11 => f.write_str("HTTP/1.1"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RequestError {
+    // The peer closed the connection before sending any byte of a new request
+    Closed,
+    Io(io::Error),
+    BadRequest(String),
+    HeaderTooLarge,
+    PayloadTooLarge,
+    NotImplemented(String),
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::Closed => write!(f, "Connection closed"),
+            RequestError::Io(e) => write!(f, "Unable to read stream: {}", e),
+            RequestError::BadRequest(msg) => write!(f, "Bad request: {}", msg),
+            RequestError::HeaderTooLarge => write!(f, "Request header fields too large"),
+            RequestError::PayloadTooLarge => write!(f, "Payload too large"),
+            RequestError::NotImplemented(msg) => write!(f, "Not implemented: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+impl From<io::Error> for RequestError {
+    fn from(e: io::Error) -> Self {
+        RequestError::Io(e)
+    }
+}
+
+#[derive(Debug)]
+pub struct Request {
+    pub method: Method,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub version: Version,
+    // Header names are stored lowercased, repeated headers are joined with ", "
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+    // Path parameters filled in by the router
+    pub params: HashMap<String, String>,
+    // Caller of an authenticated route, filled in after the token is validated
+    pub auth: Option<AuthContext>,
+    // Peer address of the connection, set by the server once the request is read
+    pub remote_addr: Option<SocketAddr>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_ascii_lowercase()).map(|v| v.as_str())
+    }
+
+    pub fn param<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.params.get(name).and_then(|v| v.parse().ok())
+    }
+
+    // HTTP/1.1 connections are persistent unless the client sends "Connection: close",
+    // HTTP/1.0 connections only when the client sends "Connection: keep-alive"
+    pub fn keep_alive(&self) -> bool {
+        let has_token = |token: &str| {
+            self.header("Connection")
+                .map(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+                .unwrap_or(false)
+        };
+        match self.version {
+            Version::Http11 => !has_token("close"),
+            Version::Http10 => has_token("keep-alive"),
+        }
+    }
+}
+
+// Read one HTTP/1.1 request from the stream. Headers are read until CRLFCRLF and the body
+// is read according to Content-Length or chunked Transfer-Encoding. Any bytes after the
+// body stay in the reader for the next request.
+pub async fn read_request<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Request, RequestError> {
+    let mut header_budget = MAX_HEADER_SIZE;
+
+    // Tolerate empty lines before the request line (RFC 9112 section 2.2)
+    let request_line = loop {
+        let line = read_line(reader, &mut header_budget).await?;
+        if !line.is_empty() {
+            break line;
+        }
+    };
+
+    let mut parts = request_line.split(' ');
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version), None) => (method, target, version),
+        _ => return Err(RequestError::BadRequest("Malformed request line".to_string())),
+    };
+    let version = match version {
+        "HTTP/1.1" => Version::Http11,
+        "HTTP/1.0" => Version::Http10,
+        _ => return Err(RequestError::NotImplemented(format!("Unsupported version {}", version))),
+    };
+    let method = Method::from_str(method)?;
+    if !target.starts_with('/') {
+        return Err(RequestError::BadRequest("Malformed request target".to_string()));
+    }
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, parse_query(query)),
+        None => (target, HashMap::new()),
+    };
+
+    let mut headers: HashMap<String, String> = HashMap::new();
+    loop {
+        let line = read_line(reader, &mut header_budget).await.map_err(closed_as_bad_request)?;
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() >= MAX_HEADERS {
+            return Err(RequestError::HeaderTooLarge);
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| RequestError::BadRequest("Malformed header line".to_string()))?;
+        if name.is_empty() || name.ends_with(' ') || name.ends_with('\t') {
+            return Err(RequestError::BadRequest("Malformed header name".to_string()));
+        }
+        let name = name.to_ascii_lowercase();
+        let value = value.trim_matches(|c| c == ' ' || c == '\t');
+        headers
+            .entry(name)
+            .and_modify(|v| {
+                v.push_str(", ");
+                v.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+
+    let body = match (headers.get("transfer-encoding"), headers.get("content-length")) {
+        (Some(_), Some(_)) => {
+            return Err(RequestError::BadRequest("Both Transfer-Encoding and Content-Length are set".to_string()))
+        }
+        (Some(encoding), None) => {
+            if !encoding.eq_ignore_ascii_case("chunked") {
+                return Err(RequestError::NotImplemented(format!("Unsupported transfer encoding {}", encoding)));
+            }
+            read_chunked_body(reader).await?
+        }
+        (None, Some(length)) => {
+            let length: usize = length
+                .parse()
+                .map_err(|_| RequestError::BadRequest("Invalid Content-Length".to_string()))?;
+            if length > MAX_BODY_SIZE {
+                return Err(RequestError::PayloadTooLarge);
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await.map_err(eof_as_bad_request)?;
+            body
+        }
+        (None, None) => Vec::new(),
+    };
+
+    Ok(Request {
+        method,
+        path: path.to_string(),
+        query,
+        version,
+        headers,
+        body,
+        params: HashMap::new(),
+        auth: None,
+        remote_addr: None,
+    })
+}
+
+async fn read_chunked_body<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Vec<u8>, RequestError> {
+    let mut body = Vec::new();
+    loop {
+        let mut budget = MAX_HEADER_SIZE;
+        let line = read_line(reader, &mut budget).await.map_err(closed_as_bad_request)?;
+        // Chunk extensions after ';' are ignored
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| RequestError::BadRequest("Invalid chunk size".to_string()))?;
+        if size == 0 {
+            break;
+        }
+        if size > MAX_BODY_SIZE - body.len() {
+            return Err(RequestError::PayloadTooLarge);
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..]).await.map_err(eof_as_bad_request)?;
+        let mut crlf = [0; 2];
+        reader.read_exact(&mut crlf).await.map_err(eof_as_bad_request)?;
+        if &crlf != b"\r\n" {
+            return Err(RequestError::BadRequest("Malformed chunk".to_string()));
+        }
+    }
+
+    // Trailer fields are read and discarded
+    let mut budget = MAX_HEADER_SIZE;
+    while !read_line(reader, &mut budget).await.map_err(closed_as_bad_request)?.is_empty() {}
+    Ok(body)
+}
+
+// Read a single CRLF (or bare LF) terminated line, charging its length against the budget.
+// Returns RequestError::Closed when the stream is already at EOF.
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R, budget: &mut usize) -> Result<String, RequestError> {
+    let mut line = Vec::new();
+    let limit = (*budget + 1) as u64;
+    let read = (&mut *reader).take(limit).read_until(b'\n', &mut line).await?;
+    if read == 0 {
+        return Err(RequestError::Closed);
+    }
+    // Up to budget + 1 bytes are read, so a line ending exactly one byte past the budget
+    // still has its newline
+    if read > *budget {
+        return Err(RequestError::HeaderTooLarge);
+    }
+    if !line.ends_with(b"\n") {
+        return Err(RequestError::BadRequest("Unexpected end of stream".to_string()));
+    }
+    *budget -= read;
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    String::from_utf8(line).map_err(|_| RequestError::BadRequest("Header is not valid UTF-8".to_string()))
+}
+
+fn closed_as_bad_request(e: RequestError) -> RequestError {
+    match e {
+        RequestError::Closed => RequestError::BadRequest("Unexpected end of stream".to_string()),
+        e => e,
+    }
+}
+
+fn eof_as_bad_request(e: io::Error) -> RequestError {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        RequestError::BadRequest("Unexpected end of stream".to_string())
+    } else {
+        RequestError::Io(e)
+    }
+}
+
+pub fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) => (percent_decode(key), percent_decode(value)),
+            None => (percent_decode(pair), String::new()),
+        })
+        .collect()
+}
+
+pub fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() && bytes[i + 1].is_ascii_hexdigit() && bytes[i + 2].is_ascii_hexdigit() => {
+                let hex = [bytes[i + 1], bytes[i + 2]];
+                let hex = std::str::from_utf8(&hex).unwrap_or_default();
+                decoded.push(u8::from_str_radix(hex, 16).unwrap_or_default());
+                i += 2;
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read(input: &str) -> Result<Request, RequestError> {
+        read_request(&mut input.as_bytes()).await
+    }
+
+    #[tokio::test]
+    async fn reads_body_of_content_length() {
+        let request = read("POST /users?page=2 HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhello").await.unwrap();
+        assert_eq!(request.method, Method::Post);
+        assert_eq!(request.path, "/users");
+        assert_eq!(request.query.get("page").map(String::as_str), Some("2"));
+        assert_eq!(request.body, b"hello");
+    }
+
+    #[tokio::test]
+    async fn reads_chunked_body_and_discards_trailers() {
+        let input = "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+            4;ext=1\r\nWiki\r\n5\r\npedia\r\n0\r\nExpires: never\r\n\r\nGET /next HTTP/1.1\r\n\r\n";
+        let mut reader = input.as_bytes();
+        let request = read_request(&mut reader).await.unwrap();
+        assert_eq!(request.body, b"Wikipedia");
+        assert_eq!(request.header("Expires"), None);
+        assert_eq!(read_request(&mut reader).await.unwrap().path, "/next");
+    }
+
+    #[tokio::test]
+    async fn rejects_transfer_encoding_with_content_length() {
+        let input = "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n0\r\n\r\n";
+        assert!(matches!(read(input).await, Err(RequestError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn accepts_header_of_exactly_the_budget() {
+        // Request line (16 bytes), header line (9 + pad bytes) and the empty line (2 bytes)
+        let pad = "a".repeat(MAX_HEADER_SIZE - 27);
+        let request = read(&format!("GET / HTTP/1.1\r\nX-Pad: {}\r\n\r\n", pad)).await.unwrap();
+        assert_eq!(request.header("X-Pad").map(str::len), Some(pad.len()));
+    }
+
+    #[tokio::test]
+    async fn rejects_header_one_byte_over_the_budget() {
+        let pad = "a".repeat(MAX_HEADER_SIZE - 26);
+        let input = format!("GET / HTTP/1.1\r\nX-Pad: {}\r\n\r\n", pad);
+        assert!(matches!(read(&input).await, Err(RequestError::HeaderTooLarge)));
+    }
+
+    #[tokio::test]
+    async fn rejects_line_ending_one_byte_past_the_budget() {
+        let mut budget = 4;
+        assert!(matches!(read_line(&mut "abcd\n".as_bytes(), &mut budget).await, Err(RequestError::HeaderTooLarge)));
+        assert_eq!(budget, 4);
+    }
+
+    #[tokio::test]
+    async fn leaves_pipelined_requests_in_the_reader() {
+        let mut reader = "POST /a HTTP/1.1\r\nContent-Length: 2\r\n\r\nhiGET /b HTTP/1.1\r\n\r\n".as_bytes();
+        let first = read_request(&mut reader).await.unwrap();
+        assert_eq!((first.path.as_str(), first.body.as_slice()), ("/a", &b"hi"[..]));
+        let second = read_request(&mut reader).await.unwrap();
+        assert_eq!((second.method, second.path.as_str()), (Method::Get, "/b"));
+        assert!(matches!(read_request(&mut reader).await, Err(RequestError::Closed)));
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+use super::token::AuthContext;
+
+// In-memory copy of the revoked access tokens, so authenticating does not hit the database.
+// Maps the jti of a token to its exp, after which the entry is no longer needed.
+#[derive(Debug, Default)]
+pub struct RevocationList {
+    entries: RwLock<HashMap<String, i64>>,
+    // Users whose every token issued before a time is revoked, as user id to
+    // (revoked_before, expires_at) pairs
+    users: RwLock<HashMap<i32, (i64, i64)>>,
+}
+
+impl RevocationList {
+    pub fn new() -> Self {
+        RevocationList::default()
+    }
+
+    pub fn revoke(&self, jti: String, exp: i64) {
+        self.entries.write().unwrap().insert(jti, exp);
+    }
+
+    pub fn revoke_user(&self, user_id: i32, revoked_before: i64, expires_at: i64) {
+        let mut users = self.users.write().unwrap();
+        let entry = users.entry(user_id).or_insert((revoked_before, expires_at));
+        *entry = (entry.0.max(revoked_before), entry.1.max(expires_at));
+    }
+
+    pub fn is_revoked(&self, auth: &AuthContext) -> bool {
+        if self.entries.read().unwrap().contains_key(&auth.jti) {
+            return true;
+        }
+        match self.users.read().unwrap().get(&auth.user_id) {
+            Some((revoked_before, _)) => auth.issued_at < *revoked_before,
+            None => false,
+        }
+    }
+
+    // Drops entries expired at now and adds the ones loaded from the database,
+    // which also picks up tokens revoked by other instances
+    pub fn merge(&self, entries: Vec<(String, i64)>, users: Vec<(i32, i64, i64)>, now: i64) {
+        let mut current = self.entries.write().unwrap();
+        current.retain(|_, exp| *exp > now);
+        current.extend(entries.into_iter().filter(|(_, exp)| *exp > now));
+        drop(current);
+
+        self.users.write().unwrap().retain(|_, (_, expires_at)| *expires_at > now);
+        for (user_id, revoked_before, expires_at) in users {
+            if expires_at > now {
+                self.revoke_user(user_id, revoked_before, expires_at);
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use super::request::{percent_decode, Method, Request};
+use super::role::Role;
+use super::response::Response;
+
+pub type HandlerFuture<'a> = Pin<Box<dyn Future<Output = Response> + Send + 'a>>;
+pub type Handler<S> = for<'a> fn(&'a Request, &'a S) -> HandlerFuture<'a>;
+
+// Class of rate limit applied to a route before its handler runs, each class has its own
+// per client quota from config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Limiter {
+    Common,
+    // Endpoints that check passwords or codes
+    Hard,
+    // Registration and the other unauthenticated endpoints that send email
+    Register,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParamKind {
+    Any,
+    Int,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Static(String),
+    Param(String, ParamKind),
+}
+
+pub struct Route<S> {
+    pub method: Method,
+    pub template: String,
+    pub handler: Handler<S>,
+    pub authenticated: bool,
+    pub limiter: Option<Limiter>,
+    // Roles allowed to call the route, None lets any authenticated caller through
+    roles: Option<&'static [Role]>,
+    // Path parameter holding a user id, whose owner may call the route whatever their role
+    owner_param: Option<&'static str>,
+    segments: Vec<Segment>,
+}
+
+impl<S> Route<S> {
+    // Require a valid bearer token before the handler runs
+    pub fn authenticated(&mut self) -> &mut Self {
+        self.authenticated = true;
+        self
+    }
+
+    pub fn limit(&mut self, limiter: Limiter) -> &mut Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    // Restrict the route to callers holding one of the roles, implies authenticated
+    pub fn allow(&mut self, roles: &'static [Role]) -> &mut Self {
+        self.authenticated = true;
+        self.roles = Some(roles);
+        self
+    }
+
+    // Also let the user whose id is in the path parameter through, implies authenticated
+    pub fn or_owner(&mut self, param: &'static str) -> &mut Self {
+        self.authenticated = true;
+        self.owner_param = Some(param);
+        self
+    }
+
+    // Whether the authenticated caller of the request may use this route
+    pub fn permits(&self, request: &Request) -> bool {
+        let auth = match &request.auth {
+            Some(auth) => auth,
+            None => return !self.authenticated,
+        };
+        // Read-only accounts may read their own record but not change it
+        let read_only = auth.has_role(Role::ReadOnly) && !matches!(self.method, Method::Get | Method::Head);
+        let owner = !read_only && self.owner_param
+            .and_then(|param| request.param::<i32>(param))
+            .is_some_and(|id| id == auth.user_id);
+        let role = match self.roles {
+            Some(roles) => roles.iter().any(|role| auth.has_role(*role)),
+            None => self.owner_param.is_none(),
+        };
+        owner || role
+    }
+
+    fn matches(&self, path_segments: &[&str]) -> Option<HashMap<String, String>> {
+        if self.segments.len() != path_segments.len() {
+            return None;
+        }
+        let mut params = HashMap::new();
+        for (segment, value) in self.segments.iter().zip(path_segments) {
+            match segment {
+                Segment::Static(s) => {
+                    if s != value {
+                        return None;
+                    }
+                }
+                Segment::Param(name, kind) => {
+                    let value = percent_decode(value);
+                    let valid = match kind {
+                        ParamKind::Any => !value.is_empty(),
+                        // Handlers read ids as i32, larger values could never be found
+                        ParamKind::Int => value.parse::<i32>().is_ok(),
+                    };
+                    if !valid {
+                        return None;
+                    }
+                    params.insert(name.clone(), value);
+                }
+            }
+        }
+        Some(params)
+    }
+}
+
+pub enum Resolved<'r, S> {
+    Found(&'r Route<S>, HashMap<String, String>),
+    // The path exists but not for this method, carries the methods that are allowed
+    MethodNotAllowed(Vec<Method>),
+    NotFound,
+}
+
+// Routes are registered with path templates such as "/users/{id:int}". A "{name}" segment
+// matches any non-empty segment and "{name:int}" only an integer that fits in an i32. Routes are tried in
+// registration order, so register static paths before overlapping parameterized ones.
+pub struct Router<S> {
+    routes: Vec<Route<S>>,
+}
+
+impl<S> Router<S> {
+    pub fn new() -> Self {
+        Router { routes: Vec::new() }
+    }
+
+    pub fn route(&mut self, method: Method, template: &str, handler: Handler<S>) -> &mut Route<S> {
+        self.routes.push(Route {
+            method,
+            template: template.to_string(),
+            handler,
+            authenticated: false,
+            limiter: None,
+            roles: None,
+            owner_param: None,
+            segments: parse_template(template),
+        });
+        self.routes.last_mut().unwrap()
+    }
+
+    pub fn get(&mut self, template: &str, handler: Handler<S>) -> &mut Route<S> {
+        self.route(Method::Get, template, handler)
+    }
+
+    pub fn post(&mut self, template: &str, handler: Handler<S>) -> &mut Route<S> {
+        self.route(Method::Post, template, handler)
+    }
+
+    pub fn put(&mut self, template: &str, handler: Handler<S>) -> &mut Route<S> {
+        self.route(Method::Put, template, handler)
+    }
+
+    pub fn patch(&mut self, template: &str, handler: Handler<S>) -> &mut Route<S> {
+        self.route(Method::Patch, template, handler)
+    }
+
+    pub fn delete(&mut self, template: &str, handler: Handler<S>) -> &mut Route<S> {
+        self.route(Method::Delete, template, handler)
+    }
+
+    pub fn resolve(&self, method: Method, path: &str) -> Resolved<'_, S> {
+        let path_segments = split_path(path);
+        let mut allowed = Vec::new();
+        for route in &self.routes {
+            if let Some(params) = route.matches(&path_segments) {
+                if route.method == method {
+                    return Resolved::Found(route, params);
+                }
+                if !allowed.contains(&route.method) {
+                    allowed.push(route.method);
+                }
+            }
+        }
+        if allowed.is_empty() {
+            Resolved::NotFound
+        } else {
+            allowed.push(Method::Options);
+            Resolved::MethodNotAllowed(allowed)
+        }
+    }
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    path.strip_prefix('/').unwrap_or(path).split('/').collect()
+}
+
+fn parse_template(template: &str) -> Vec<Segment> {
+    split_path(template)
+        .into_iter()
+        .map(|segment| match segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+            Some(param) => match param.split_once(':') {
+                Some((name, "int")) => Segment::Param(name.to_string(), ParamKind::Int),
+                Some((_, kind)) => panic!("Unknown parameter type '{}' in route '{}'", kind, template),
+                None => Segment::Param(param.to_string(), ParamKind::Any),
+            },
+            None => Segment::Static(segment.to_string()),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::response::StatusCode;
+
+    fn router() -> Router<()> {
+        let mut router = Router::new();
+        router.get("/users", |_, _| Box::pin(async { Response::new(StatusCode::Ok) }));
+        router.post("/users", |_, _| Box::pin(async { Response::new(StatusCode::Ok) }));
+        router.get("/users/search", |_, _| Box::pin(async { Response::new(StatusCode::Ok) }));
+        router.get("/users/{id:int}", |_, _| Box::pin(async { Response::new(StatusCode::Ok) }));
+        router.delete("/users/{id:int}", |_, _| Box::pin(async { Response::new(StatusCode::Ok) }));
+        router
+    }
+
+    fn found(router: &Router<()>, method: Method, path: &str) -> Option<(String, HashMap<String, String>)> {
+        match router.resolve(method, path) {
+            Resolved::Found(route, params) => Some((route.template.clone(), params)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn resolves_static_before_parameterized_routes() {
+        let router = router();
+        assert_eq!(found(&router, Method::Get, "/users/search").unwrap().0, "/users/search");
+        let (template, params) = found(&router, Method::Get, "/users/42").unwrap();
+        assert_eq!(template, "/users/{id:int}");
+        assert_eq!(params.get("id").map(String::as_str), Some("42"));
+    }
+
+    #[test]
+    fn lists_allowed_methods_of_known_path() {
+        match router().resolve(Method::Put, "/users/42") {
+            Resolved::MethodNotAllowed(allowed) => assert_eq!(allowed, vec![Method::Get, Method::Delete, Method::Options]),
+            _ => panic!("expected 405"),
+        }
+    }
+
+    #[test]
+    fn does_not_match_trailing_slash_or_longer_segment() {
+        let router = router();
+        assert!(matches!(router.resolve(Method::Get, "/users/"), Resolved::NotFound));
+        assert!(matches!(router.resolve(Method::Get, "/usersX"), Resolved::NotFound));
+    }
+
+    #[test]
+    fn int_params_must_fit_in_i32() {
+        let router = router();
+        assert!(found(&router, Method::Get, "/users/2147483647").is_some());
+        assert!(found(&router, Method::Get, "/users/-1").is_some());
+        assert!(matches!(router.resolve(Method::Get, "/users/2147483648"), Resolved::NotFound));
+        assert!(matches!(router.resolve(Method::Get, "/users/3000000000"), Resolved::NotFound));
+        assert!(matches!(router.resolve(Method::Get, "/users/abc"), Resolved::NotFound));
+    }
+}
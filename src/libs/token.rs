@@ -1,29 +1,82 @@
 use chrono;
-use jsonwebtoken::{encode, Header, EncodingKey, decode, DecodingKey, Validation};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use jsonwebtoken::{encode, decode, decode_header};
+use super::keyring::Keyring;
+use super::role::Role;
 use log::error;
 
+// Lifetime of an access token in seconds
+pub const ACCESS_TOKEN_TTL: i64 = 60 * 60;
+
 #[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-   email: String,
-   exp: i64,
+pub struct Claims {
+   // Id of the user
+   pub sub: String,
+   pub email: String,
+   pub iss: String,
+   pub aud: String,
+   pub iat: i64,
+   pub nbf: i64,
+   pub exp: i64,
+   // Unique id of the token, the key of the revocation list
+   pub jti: String,
+   #[serde(default)]
+   pub roles: Vec<String>,
+}
+
+// Caller of an authenticated request, taken from the claims of its access token
+#[derive(Debug, Clone)]
+pub struct AuthContext {
+    pub user_id: i32,
+    pub email: String,
+    pub roles: Vec<Role>,
+    pub jti: String,
+    pub issued_at: i64,
+    pub exp: i64,
 }
 
-pub fn claim_jwt_token(email: String) -> Result<String, Box<dyn std::error::Error>> {
-    let expiration = chrono::Utc::now() + chrono::Duration::hours(1);
+impl AuthContext {
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.contains(&role)
+    }
+}
+
+impl TryFrom<Claims> for AuthContext {
+    type Error = String;
+
+    fn try_from(claims: Claims) -> Result<Self, Self::Error> {
+        let user_id = claims.sub.parse().map_err(|_| format!("Invalid subject '{}'", claims.sub))?;
+        Ok(AuthContext {
+            user_id,
+            email: claims.email,
+            // Roles this version does not know grant nothing
+            roles: claims.roles.iter().filter_map(|role| role.parse().ok()).collect(),
+            jti: claims.jti,
+            issued_at: claims.iat,
+            exp: claims.exp,
+        })
+    }
+}
+
+pub fn claim_jwt_token(user_id: i32, email: String, roles: Vec<String>, keyring: &Keyring) -> Result<String, Box<dyn std::error::Error>> {
+    let now = chrono::Utc::now();
+    let expiration = now + chrono::Duration::seconds(ACCESS_TOKEN_TTL);
     let claims = Claims {
-        email: email,
+        sub: user_id.to_string(),
+        email,
+        iss: keyring.issuer().to_string(),
+        aud: keyring.audience().to_string(),
+        iat: now.timestamp(),
+        nbf: now.timestamp(),
         exp: expiration.timestamp(),
+        jti: generate_opaque_token(),
+        roles,
     };
 
-    let secret_key = match std::env::var("SECRET_KEY") {
-        Ok(secret_key) => secret_key,
-        Err(_) => {
-            error!("SECRET_KEY environment variable is not set");
-            return Err("SECRET_KEY environment variable is not set".into())
-        }
-    };
-
-    let token = match encode(&Header::default(), &claims, &EncodingKey::from_secret(secret_key.as_bytes())) {
+    let token = match encode(&keyring.header(), &claims, keyring.encoding_key()) {
         Ok(token) => token,
         Err(e) => {
             error!("Error generating jwt token: {:?}", e);
@@ -33,19 +86,84 @@ pub fn claim_jwt_token(email: String) -> Result<String, Box<dyn std::error::Erro
     Ok(token)
 }
 
-pub fn validate_token(token: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let secret_key = match std::env::var("SECRET_KEY") {
-        Ok(secret_key) => secret_key,
-        Err(_) => {
-            error!("SECRET_KEY environment variable is not set");
-            return Err("SECRET_KEY environment variable is not set".into())
+// Short lived token proving control of an email address, for links sent by email.
+// Purpose keeps a token made for one action from being accepted by another.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActionClaims {
+   pub sub: String,
+   pub email: String,
+   pub purpose: String,
+   pub iss: String,
+   pub aud: String,
+   pub iat: i64,
+   pub nbf: i64,
+   pub exp: i64,
+}
+
+pub fn claim_action_token(purpose: &str, user_id: i32, email: String, ttl: i64, keyring: &Keyring) -> Result<String, Box<dyn std::error::Error>> {
+    let now = chrono::Utc::now();
+    let claims = ActionClaims {
+        sub: user_id.to_string(),
+        email,
+        purpose: purpose.to_string(),
+        iss: keyring.issuer().to_string(),
+        aud: keyring.audience().to_string(),
+        iat: now.timestamp(),
+        nbf: now.timestamp(),
+        exp: (now + chrono::Duration::seconds(ttl)).timestamp(),
+    };
+
+    match encode(&keyring.header(), &claims, keyring.encoding_key()) {
+        Ok(token) => Ok(token),
+        Err(e) => {
+            error!("Error generating action token: {:?}", e);
+            Err("Error generating action token".into())
+        }
+    }
+}
+
+pub fn validate_action_token(token: &str, purpose: &str, keyring: &Keyring) -> Result<ActionClaims, Box<dyn std::error::Error>> {
+    let header = decode_header(token)?;
+    let (algorithm, key) = keyring.verification_key(header.kid.as_deref()).ok_or("Unknown key id")?;
+    let claims = decode::<ActionClaims>(token, key, &keyring.validation(algorithm))?.claims;
+    if claims.purpose != purpose {
+        return Err(format!("Token is meant for {}", claims.purpose).into());
+    }
+    Ok(claims)
+}
+
+pub fn validate_token(token: &str, keyring: &Keyring) -> Result<Claims, Box<dyn std::error::Error>> {
+    let header = match decode_header(token) {
+        Ok(header) => header,
+        Err(e) => {
+            error!("Error decoding jwt header: {:?}", e);
+            return Err("Error validating jwt token".into())
+        }
+    };
+    let (algorithm, key) = match keyring.verification_key(header.kid.as_deref()) {
+        Some(key) => key,
+        None => {
+            error!("Unknown jwt key id {:?}", header.kid);
+            return Err("Error validating jwt token".into())
         }
     };
-    match decode::<Claims>(token, &DecodingKey::from_secret(secret_key.as_bytes()), &Validation::default()) {
-        Ok(token_data) => Ok(token_data.claims.email),
+    match decode::<Claims>(token, key, &keyring.validation(algorithm)) {
+        Ok(token_data) => Ok(token_data.claims),
         Err(e) => {
             error!("Error validating jwt token: {:?}", e);
             Err("Error validating jwt token".into())
         }
     }
+}
+
+// Random URL-safe value with 256 bits of entropy, used for refresh tokens and their families
+pub fn generate_opaque_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+// Refresh tokens are stored as their SHA-256, so a leaked table cannot be replayed
+pub fn hash_opaque_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
 }
\ No newline at end of file
@@ -0,0 +1,79 @@
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+
+// RFC 6238 with the parameters every authenticator app supports: SHA-1, 6 digits, 30 second steps
+const DIGITS: u32 = 6;
+const STEP: i64 = 30;
+// Steps accepted on each side of the current one, to allow for clock drift
+const SKEW: i64 = 1;
+
+// New random 160 bit secret, base32 encoded as authenticator apps expect
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+// otpauth:// URI of the secret, usually shown to the user as a QR code
+pub fn provisioning_uri(secret: &str, issuer: &str, account: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        url_encode(issuer), url_encode(account), secret, url_encode(issuer), DIGITS, STEP
+    )
+}
+
+// Time step the code is valid for around now, None when it matches none of them.
+// Callers store the step to refuse a code being used twice.
+pub fn verify(secret: &str, code: &str, now: i64) -> Option<i64> {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let current = now / STEP;
+    (current - SKEW..=current + SKEW).find(|step| generate(&key, *step) == code)
+}
+
+fn generate(key: &[u8], step: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    // Dynamic truncation of RFC 4226
+    let offset = (digest[19] & 0x0f) as usize;
+    let value = u32::from_be_bytes([digest[offset] & 0x7f, digest[offset + 1], digest[offset + 2], digest[offset + 3]]);
+    format!("{:0width$}", value % 10u32.pow(DIGITS), width = DIGITS as usize)
+}
+
+fn url_encode(value: &str) -> String {
+    value.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Secret of the SHA-1 test vectors in RFC 6238 appendix B
+    const RFC_KEY: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn matches_rfc_6238_vectors() {
+        assert_eq!(generate(RFC_KEY, 59 / STEP), "287082");
+        assert_eq!(generate(RFC_KEY, 1111111109 / STEP), "081804");
+        assert_eq!(generate(RFC_KEY, 1234567890 / STEP), "005924");
+        assert_eq!(generate(RFC_KEY, 2000000000 / STEP), "279037");
+    }
+
+    #[test]
+    fn accepts_adjacent_steps_only() {
+        let secret = BASE32_NOPAD.encode(RFC_KEY);
+        assert_eq!(verify(&secret, "081804", 1111111109), Some(1111111109 / STEP));
+        assert_eq!(verify(&secret, "081804", 1111111109 + STEP), Some(1111111109 / STEP));
+        assert_eq!(verify(&secret, "081804", 1111111109 + 2 * STEP), None);
+        assert_eq!(verify(&secret, "81804", 1111111109), None);
+    }
+}
\ No newline at end of file
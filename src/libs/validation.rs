@@ -0,0 +1,52 @@
+use std::collections::BTreeMap;
+use super::error::{AppError, ErrorDetail};
+
+// Violations collected per field, so a single response can report every invalid field
+#[derive(Debug, Default)]
+pub struct ValidationErrors {
+    fields: BTreeMap<String, Vec<String>>,
+}
+
+impl ValidationErrors {
+    pub fn new() -> Self {
+        ValidationErrors::default()
+    }
+
+    pub fn add(&mut self, field: &str, message: &str) {
+        self.fields.entry(field.to_string()).or_default().push(message.to_string());
+    }
+
+    pub fn has(&self, field: &str) -> bool {
+        self.fields.contains_key(field)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn into_result(self) -> Result<(), AppError> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(AppError::Validation(self.into_details()))
+        }
+    }
+
+    fn into_details(self) -> Vec<ErrorDetail> {
+        self.fields
+            .into_iter()
+            .flat_map(|(field, messages)| {
+                messages.into_iter().map(move |message| ErrorDetail {
+                    field: Some(field.clone()),
+                    message,
+                })
+            })
+            .collect()
+    }
+}
+
+// Field rules of an input. Checks that need the database are added by the handler
+// to the returned errors before they are turned into a response.
+pub trait Validate {
+    fn validate(&self) -> ValidationErrors;
+}
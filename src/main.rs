@@ -1,34 +1,50 @@
 mod users;
 mod auth;
 mod libs;
+mod routes;
 
 use std::sync::Arc;
-use std::num::NonZeroU32;
+use std::net::SocketAddr;
 use std::str::FromStr; 
 use std::io::Write; 
-use governor::clock::QuantaClock;
-use governor::state::{InMemoryState, NotKeyed};
-use tokio::net::TcpListener;
-use tokio::sync::Semaphore;
+use tokio::net::{TcpListener, TcpStream};
 use tokio_postgres::{Config, NoTls};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::time::{Duration, sleep};
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::time::{Duration, timeout};
 use tokio::signal;
-use governor::{Quota, RateLimiter};
+use tokio::sync::Semaphore;
 use deadpool_postgres::{Manager, Pool};
 use log::{info, error, debug};
-use users::handler::{ create_user, get_user, list_user, edit_user, delete_user };
-use auth::handler::login_user;
-use libs::{ get_db_url, authenticate, NOT_FOUND, CORS_ALLOW_ALL, TOO_MANY_REQUEST, UNAUTHORIZED };
+use libs::{ get_db_url, authenticate };
+use libs::admission::Admission;
+use libs::config::Config as AppConfig;
+use libs::error::AppError;
+use libs::keyring::Keyring;
+use libs::mailer::{self, Mailer};
+use libs::password::PasswordPolicy;
+use libs::rate_limit::{RateLimitStatus, RateLimits};
+use libs::revocation::RevocationList;
+use libs::response::{ Response, StatusCode };
+use libs::request::{ read_request, Method, Request, RequestError };
+use libs::router::{ Resolved, Router };
 
 
 #[macro_use]
 extern crate serde_derive;
 
+const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REQUESTS_PER_CONNECTION: usize = 100;
+
 struct AppState {
     db_pool: Pool,
-    common_limiter: Arc<RateLimiter<NotKeyed, InMemoryState, QuantaClock>>,
-    hard_limiter: Arc<RateLimiter<NotKeyed, InMemoryState, QuantaClock>>,
+    admission: Admission,
+    rate_limits: RateLimits,
+    password_policy: PasswordPolicy,
+    keyring: Keyring,
+    mailer: Arc<dyn Mailer>,
+    revoked_tokens: RevocationList,
+    config: AppConfig,
+    router: Router<AppState>,
 }
 
 #[tokio::main]
@@ -39,6 +55,11 @@ async fn main() {
     let manager = Manager::new(cfg, NoTls);
     let pool = Pool::new(manager, 16); // 16 adalah ukuran maksimum pool
 
+    let config = AppConfig::from_env();
+    let password_policy = PasswordPolicy::from_config(&config.password).expect("Failed to load password policy");
+    let keyring = Keyring::from_config(&config.token).expect("Failed to load JWT keys");
+    auth::handler::login_user::dummy_hash();
+
     env_logger::Builder::new()
         .filter_level(log::LevelFilter::Debug)  
         .format(|buf: &mut env_logger::fmt::Formatter, record| {
@@ -53,38 +74,43 @@ async fn main() {
 
     //start server and print port
     let listener = TcpListener::bind("0.0.0.0:8080").await.expect("Failed to bind to address 0.0.0.0:8080");
-    let semaphore = Arc::new(Semaphore::new(10));
-    let global_limiter = Arc::new(RateLimiter::direct(Quota::per_second(NonZeroU32::new(200).unwrap())));
-    let common_limiter = Arc::new(RateLimiter::direct(Quota::per_second(NonZeroU32::new(100).unwrap())));
-    let hard_limiter = Arc::new(RateLimiter::direct(Quota::per_second(NonZeroU32::new(100).unwrap())));
+    let admission = Admission::from_config(&config.admission);
+    let connections = Arc::new(Semaphore::new(config.admission.max_connections.max(1)));
+    let rate_limits = RateLimits::from_config(&config.rate_limit, &pool);
     info!("Server listening on port 8080");
 
+    // Load tokens revoked before this start, so they are rejected from the first request
+    let revoked_tokens = RevocationList::new();
+    auth::token_sweeper::sweep(&pool, &revoked_tokens).await;
+
     // Share AppState with all incoming connections
     let app_state = Arc::new(AppState {
         db_pool: pool,
-        common_limiter: common_limiter.clone(),
-        hard_limiter: hard_limiter.clone(),
+        admission,
+        rate_limits,
+        password_policy,
+        keyring,
+        mailer: mailer::from_config(&config.mail),
+        revoked_tokens,
+        config,
+        router: routes::router(),
     });
     
+    tokio::spawn(auth::token_sweeper::run(app_state.clone()));
+    let limits_state = app_state.clone();
+    tokio::spawn(async move { limits_state.rate_limits.run_eviction().await });
+
     let shutdown_signal = signal::ctrl_c(); 
 
     let server_task = tokio::spawn(async move {
         loop {
-            let (mut stream, _) = listener.accept().await.expect("Failed to accept connection");
-            let permit = semaphore.clone().acquire_owned().await.expect("Failed to acquire semaphore permit"); 
+            // Held until the connection closes, no new one is accepted while all are taken
+            let permit = connections.clone().acquire_owned().await.expect("Connection semaphore closed");
+            let (stream, peer) = listener.accept().await.expect("Failed to accept connection");
             let state = app_state.clone();
-            let global_limiter = global_limiter.clone();
-                
+
             tokio::spawn(async move {
-                loop {
-                    match global_limiter.check() {
-                        Ok(()) => {
-                            handle_client(&mut stream, state).await; 
-                            break;
-                        },
-                        Err(_) => sleep(Duration::from_millis(100)).await
-                    }                
-                }
+                handle_connection(stream, peer, state).await;
                 drop(permit);
             });
         }
@@ -94,64 +120,144 @@ async fn main() {
     server_task.abort(); 
 }
 
-async fn handle_client(stream: &mut tokio::net::TcpStream, state: Arc<AppState>) {
-    let mut buffer = [0; 1024];
-    let mut request = String::new();
-    match stream.read(&mut buffer).await {
-        Ok(size) => {
-            request.push_str(String::from_utf8_lossy(&buffer[..size]).as_ref());
-            let mut client = state.db_pool.get().await.expect("Failed to get a database connection from the pool");
-            let (status_line, content) = match &*request {
-                r if r.starts_with("OPTIONS") => (CORS_ALLOW_ALL.to_string(),"".to_string()),
-                r if r.starts_with("POST /users") => {
-                    match authenticate(&request).await {
-                        Ok(_email) => {
-                            debug!("email {} authenticated", _email);
-                            match state.hard_limiter.check() {
-                                Ok(()) => create_user::handle(r, &mut client).await,
-                                Err(_) => (TOO_MANY_REQUEST.to_string(), "Too Many Requests".to_string())
-                            }
-                        }
-                        Err(_) => {
-                            error!("Unauthorized access");
-                            (UNAUTHORIZED.to_string(), "Unauthorized".to_string())
-                        }
-                    }
-                },
-                r if r.starts_with("GET /users/") => get_user::handle(r, &client).await,
-                r if r.starts_with("GET /users") => list_user::handle(r, &client).await,
-                r if r.starts_with("PUT /users/") => {
-                    match state.common_limiter.check() {
-                        Ok(()) => edit_user::handle(r, &client).await,
-                        Err(_) => {
-                            error!("429 Too Many Requests");
-                            (NOT_FOUND.to_string(), "429 Too Many Requests".to_string())
-                        }
-                        
-                    }
-                },
-                r if r.starts_with("DELETE /users/") => {
-                    match state.common_limiter.check() {
-                        Ok(()) => delete_user::handle(r, &client).await,
-                        Err(_) => {
-                            error!("429 Too Many Requests");
-                            (NOT_FOUND.to_string(), "429 Too Many Requests".to_string())
-                        }
-                    }
-                },
-                r if r.starts_with("POST /login") => {
-                    match state.hard_limiter.check() {
-                        Ok(()) => login_user::handle(r, &client).await,
-                        Err(_) => {
-                            error!("429 Too Many Requests");
-                            (NOT_FOUND.to_string(), "429 Too Many Requests".to_string())
-                        }   
-                    }
-                },
-                _ => (NOT_FOUND.to_string(), "404 not found".to_string()),
-            };
-            stream.write_all(format!("{}{}", status_line, content).as_bytes()).await.expect("Failed to write response to stream");
+// Serve requests on one connection until the client asks to close it, the connection
+// stays idle for KEEP_ALIVE_TIMEOUT or MAX_REQUESTS_PER_CONNECTION requests were served.
+// Pipelined requests are answered in order because each one is read only after the
+// previous response has been written.
+async fn handle_connection(
+    stream: TcpStream,
+    peer: SocketAddr,
+    state: Arc<AppState>,
+) {
+    let mut reader = BufReader::new(stream);
+    let mut served = 0;
+
+    loop {
+        let mut request = match timeout(KEEP_ALIVE_TIMEOUT, read_request(&mut reader)).await {
+            Ok(Ok(request)) => request,
+            Ok(Err(RequestError::Closed)) => return,
+            Ok(Err(RequestError::Io(e))) => {
+                eprintln!("Unable to read stream: {}", e);
+                return;
+            }
+            Ok(Err(e)) => {
+                error!("Failed to parse request: {}", e);
+                let e = match e {
+                    RequestError::HeaderTooLarge => AppError::HeaderTooLarge,
+                    RequestError::PayloadTooLarge => AppError::PayloadTooLarge,
+                    RequestError::NotImplemented(msg) => AppError::NotImplemented(msg),
+                    e => AppError::BadRequest(e.to_string()),
+                };
+                let _ = write_response(&mut reader, e.into(), false).await;
+                return;
+            }
+            Err(_) => {
+                debug!("Closing idle connection");
+                return;
+            }
+        };
+        request.remote_addr = Some(peer);
+        debug!("{} {} {} query={:?} from {}", request.method, request.path, request.version, request.query, peer);
+        served += 1;
+        let keep_alive = request.keep_alive() && served < MAX_REQUESTS_PER_CONNECTION;
+
+        let response = handle_request(request, &state).await;
+
+        if let Err(e) = write_response(&mut reader, response, keep_alive).await {
+            error!("Failed to write response to stream: {}", e);
+            return;
+        }
+        if !keep_alive {
+            return;
+        }
+    }
+}
+
+async fn write_response(stream: &mut BufReader<TcpStream>, mut response: Response, keep_alive: bool) -> std::io::Result<()> {
+    if keep_alive {
+        response.set_header("Connection", "keep-alive");
+        response.set_header("Keep-Alive", format!("timeout={}, max={}", KEEP_ALIVE_TIMEOUT.as_secs(), MAX_REQUESTS_PER_CONNECTION));
+    } else {
+        response.set_header("Connection", "close");
+    }
+    stream.write_all(&response.to_bytes()).await?;
+    stream.flush().await
+}
+
+async fn handle_request(request: Request, state: &AppState) -> Response {
+    if request.method == Method::Options {
+        return Response::new(StatusCode::Ok)
+            .with_header("Access-Control-Allow-Origin", "*")
+            .with_header("Access-Control-Allow-Methods", "GET, POST, PUT, PATCH, DELETE, OPTIONS")
+            .with_header("Access-Control-Allow-Headers", "Content-Type");
+    }
+
+    // Admitted before the token and quota are checked, so requests waiting on the rate
+    // limit store are bounded by the queue and turned away with a 503 like the others
+    match state.admission.admit().await {
+        Ok(_permit) => route_request(request, state).await,
+        Err(e) => {
+            error!("503 Service Unavailable: {}", e);
+            e.into()
+        }
+    }
+}
+
+async fn route_request(mut request: Request, state: &AppState) -> Response {
+
+    let route = match state.router.resolve(request.method, &request.path) {
+        Resolved::Found(route, params) => {
+            debug!("Matched route {} {} with params {:?}", route.method, route.template, params);
+            request.params = params;
+            route
+        }
+        Resolved::MethodNotAllowed(allowed) => {
+            let allowed: Vec<&str> = allowed.iter().map(|m| m.as_str()).collect();
+            let response: Response = AppError::MethodNotAllowed.into();
+            let response = response.with_header("Allow", allowed.join(", "));
+            return with_rate_limit(state.rate_limits.check::<AppState>(None, &request).await, response);
+        }
+        Resolved::NotFound => {
+            let response = AppError::NotFound("Resource not found".to_string()).into();
+            return with_rate_limit(state.rate_limits.check::<AppState>(None, &request).await, response);
+        }
+    };
+
+    // The token is checked before the quota is counted, so the request counts per user when
+    // it is valid and per IP otherwise, but only rejected after so bad tokens count as well
+    let mut authenticated = true;
+    if route.authenticated {
+        match authenticate(&request, &state.keyring, &state.revoked_tokens).await {
+            Ok(auth) => {
+                debug!("user {} ({}) authenticated with roles {:?}", auth.user_id, auth.email, auth.roles);
+                request.auth = Some(auth);
+            }
+            Err(_) => authenticated = false,
         }
-        Err(e) => eprintln!("Unable to read stream: {}", e),
     }
-}
\ No newline at end of file
+
+    let rate_limit = state.rate_limits.check(Some(route), &request).await;
+    if let Some(status) = rate_limit.filter(|status| status.is_limited()) {
+        error!("429 Too Many Requests");
+        return status.apply(AppError::TooManyRequests.into());
+    }
+    if !authenticated {
+        error!("Unauthorized access");
+        return with_rate_limit(rate_limit, AppError::Unauthorized("Unauthorized".to_string()).into());
+    }
+    if !route.permits(&request) {
+        error!("Forbidden access to {} {}", route.method, route.template);
+        let response = AppError::Forbidden("You are not allowed to access this resource".to_string()).into();
+        return with_rate_limit(rate_limit, response);
+    }
+
+    let response = (route.handler)(&request, state).await;
+    with_rate_limit(rate_limit, response)
+}
+
+fn with_rate_limit(rate_limit: Option<RateLimitStatus>, response: Response) -> Response {
+    match rate_limit {
+        Some(status) => status.apply(response),
+        None => response,
+    }
+}
@@ -0,0 +1,67 @@
+use crate::AppState;
+use crate::libs::role::Role;
+use crate::libs::router::{Limiter, Router};
+use crate::users::handler::{ create_user, get_user, list_user, search_user, edit_user, patch_user, delete_user, change_password, unlock_user };
+use crate::auth::handler::{ confirm_mfa, enroll_mfa, forgot_password, login_user, logout_user, refresh_token, register_user, resend_verification, reset_password, verify_email, verify_mfa };
+
+// Registration table for every endpoint of the API
+pub fn router() -> Router<AppState> {
+    let mut router = Router::new();
+
+    router.post("/users", |request, state| Box::pin(create_user::handle(request, state)))
+        .allow(&[Role::Admin])
+        .limit(Limiter::Hard);
+    router.get("/users", |request, state| Box::pin(list_user::handle(request, state)))
+        .allow(&[Role::Admin, Role::ReadOnly]);
+    router.get("/users/search", |request, state| Box::pin(search_user::handle(request, state)))
+        .allow(&[Role::Admin, Role::ReadOnly]);
+    router.get("/users/{id:int}", |request, state| Box::pin(get_user::handle(request, state)))
+        .allow(&[Role::Admin, Role::ReadOnly])
+        .or_owner("id");
+    router.put("/users/{id:int}", |request, state| Box::pin(edit_user::handle(request, state)))
+        .allow(&[Role::Admin])
+        .or_owner("id")
+        .limit(Limiter::Common);
+    router.patch("/users/{id:int}", |request, state| Box::pin(patch_user::handle(request, state)))
+        .allow(&[Role::Admin])
+        .or_owner("id")
+        .limit(Limiter::Common);
+    router.delete("/users/{id:int}", |request, state| Box::pin(delete_user::handle(request, state)))
+        .allow(&[Role::Admin])
+        .limit(Limiter::Common);
+    router.post("/users/{id:int}/unlock", |request, state| Box::pin(unlock_user::handle(request, state)))
+        .allow(&[Role::Admin])
+        .limit(Limiter::Common);
+    // Only the account itself, checked by the handler
+    router.put("/users/{id:int}/password", |request, state| Box::pin(change_password::handle(request, state)))
+        .authenticated()
+        .limit(Limiter::Hard);
+
+    router.post("/auth/register", |request, state| Box::pin(register_user::handle(request, state)))
+        .limit(Limiter::Register);
+    router.get("/auth/verify-email", |request, state| Box::pin(verify_email::handle(request, state)))
+        .limit(Limiter::Common);
+    router.post("/auth/verify-email/resend", |request, state| Box::pin(resend_verification::handle(request, state)))
+        .limit(Limiter::Register);
+    router.post("/auth/forgot-password", |request, state| Box::pin(forgot_password::handle(request, state)))
+        .limit(Limiter::Register);
+    router.post("/auth/reset-password", |request, state| Box::pin(reset_password::handle(request, state)))
+        .limit(Limiter::Hard);
+    router.post("/login", |request, state| Box::pin(login_user::handle(request, state)))
+        .limit(Limiter::Hard);
+    router.post("/auth/mfa/verify", |request, state| Box::pin(verify_mfa::handle(request, state)))
+        .limit(Limiter::Hard);
+    router.post("/auth/mfa/enroll", |request, state| Box::pin(enroll_mfa::handle(request, state)))
+        .authenticated()
+        .limit(Limiter::Common);
+    router.post("/auth/mfa/confirm", |request, state| Box::pin(confirm_mfa::handle(request, state)))
+        .authenticated()
+        .limit(Limiter::Hard);
+    router.post("/auth/refresh", |request, state| Box::pin(refresh_token::handle(request, state)))
+        .limit(Limiter::Common);
+    router.post("/auth/logout", |request, state| Box::pin(logout_user::handle(request, state)))
+        .authenticated()
+        .limit(Limiter::Common);
+
+    router
+}
\ No newline at end of file
@@ -1,3 +1,3 @@
 pub mod handler;
-mod model;
+pub mod model;
 pub mod repository;
\ No newline at end of file
@@ -1,6 +1,10 @@
+pub mod change_password;
 pub mod create_user;
 pub mod delete_user;
 pub mod edit_user;
 pub mod get_user;
 pub mod list_user;
-mod util;
\ No newline at end of file
+pub mod patch_user;
+pub mod search_user;
+pub mod unlock_user;
+pub mod util;
\ No newline at end of file
@@ -0,0 +1,97 @@
+use crate::libs::request::Request;
+use crate::AppState;
+use crate::auth::session::revoke_all_sessions;
+use crate::libs::error::{AppError, ErrorDetail};
+use crate::libs::response::{Response, StatusCode};
+use crate::libs::validation::{Validate, ValidationErrors};
+use super::create_user::check_password_policy;
+use super::super::model::PasswordChangeInput;
+use super::super::repository::{get_password_by_id, get_user_by_id, update_password};
+use log::{error, info};
+use bcrypt;
+
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let id = match request.param::<i32>("id") {
+        Some(id) => id,
+        None => return AppError::BadRequest("Invalid user id".to_string()).into(),
+    };
+    // Nobody, admins included, may set the password of another account here,
+    // while read-only accounts may still change their own
+    if request.auth.as_ref().is_none_or(|auth| auth.user_id != id) {
+        return AppError::Forbidden("You can only change your own password".to_string()).into();
+    }
+    let input: PasswordChangeInput = match serde_json::from_slice(&request.body) {
+        Ok(input) => input,
+        Err(e) => return AppError::BadRequest(format!("Failed to parse request body: {}", e)).into(),
+    };
+    if let Err(e) = input.validate().into_result() {
+        return e.into();
+    }
+
+    let mut db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    let (user, hash) = match (get_user_by_id(&id, &db).await, get_password_by_id(&id, &db).await) {
+        (Ok(user), Ok(hash)) => (user, hash),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("Error getting user with id '{}': {}", id, e);
+            return AppError::NotFound(format!("User with id '{}' not found", id)).into();
+        }
+    };
+    match bcrypt::verify(&input.current_password, hash.trim_end()) {
+        Ok(true) => {}
+        Ok(false) => {
+            return AppError::Validation(vec![ErrorDetail {
+                field: Some("current_password".to_string()),
+                message: "Current password is incorrect".to_string(),
+            }]).into();
+        }
+        Err(e) => {
+            error!("Error verifying password: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    }
+
+    let mut errors = ValidationErrors::new();
+    check_password_policy(&state.password_policy, &input.password, &user.name, &user.email, &mut errors);
+    if let Err(e) = errors.into_result() {
+        return e.into();
+    }
+
+    let hash_password = match bcrypt::hash(&input.password, bcrypt::DEFAULT_COST) {
+        Ok(hash_password) => hash_password,
+        Err(e) => {
+            error!("Error hashing password: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+    let tx = match db.transaction().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {:?}", e);
+            return AppError::Internal("Failed to start transaction".to_string()).into();
+        }
+    };
+    if let Err(e) = update_password(&id, &hash_password, &*tx).await {
+        error!("Error updating password of user {}: {:?}", id, e);
+        return AppError::Internal("Internal error".to_string()).into();
+    }
+    // Including the session of this request, the caller logs in again with the new password
+    let revocation = match revoke_all_sessions(id, &*tx).await {
+        Ok(revocation) => revocation,
+        Err(e) => return e.into(),
+    };
+    if let Err(e) = tx.commit().await {
+        error!("Failed to commit transaction: {:?}", e);
+        return AppError::Internal("Failed to commit transaction".to_string()).into();
+    }
+    revocation.apply(state);
+
+    info!("User {} changed their password", id);
+    Response::new(StatusCode::NoContent)
+}
\ No newline at end of file
@@ -1,27 +1,36 @@
-use tokio_postgres::Client;
+use crate::libs::request::Request;
 use log::error;
-use crate::libs::{ get_id, INTERNAL_ERROR, NO_CONTENT, NOT_FOUND };
+use crate::AppState;
+use crate::libs::error::AppError;
+use crate::libs::response::{Response, StatusCode};
 use super::super::repository::{delete_user_by_id, get_user_by_id};
 
-pub async fn handle(request: &str, db: &Client) -> (String, String) {
-    match get_id(&request).parse::<i32>() {
-        Ok(id) => {
-            match get_user_by_id(&id, db).await  {
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+    match request.param::<i32>("id") {
+        Some(id) => {
+            match get_user_by_id(&id, &db).await  {
                 Ok(_) => {},
                 Err(e) => {
                     error!("Error getting user with id '{}': {}", id, e);
-                    return (NOT_FOUND.to_string(), "User not found".to_string())
+                    return AppError::NotFound("User not found".to_string()).into()
                 }
             }
-            match delete_user_by_id(&id, db).await {
-                Ok(_) => (NO_CONTENT.to_string(), "".to_string()),
+            match delete_user_by_id(&id, &db).await {
+                Ok(_) => Response::new(StatusCode::NoContent),
                 Err(e) => {
                     error!("Error deleting user with id '{}': {}", id, e);
-                    (INTERNAL_ERROR.to_string(), "Failed to delete user".to_string())
+                    AppError::Internal("Failed to delete user".to_string()).into()
                 }
                 
             }
         }
-        _ => (INTERNAL_ERROR.to_string(), "Internal error".to_string()),
+        None => AppError::BadRequest("Invalid user id".to_string()).into(),
     }
 }
\ No newline at end of file
@@ -1,76 +1,81 @@
-use tokio_postgres::Client;
+use crate::libs::request::Request;
 use super::util::get_user_update_input;
-use crate::libs::{ get_id, BAD_REQUEST, INTERNAL_ERROR, NOT_FOUND, OK_RESPONSE };
+use crate::AppState;
+use crate::libs::error::AppError;
+use crate::libs::response::{Response, StatusCode};
 use super::super::repository::{update_user, get_user_by_id};
 use log::error;
 use super::super::model::UserUpdateInput;
+use crate::libs::validation::Validate;
 
-pub async fn handle(request: &str, db: &Client) -> (String, String) {
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
     match
         (
-            get_id(&request).parse::<i32>(),
-            get_user_update_input(&request),
+            request.param::<i32>("id"),
+            get_user_update_input(request),
         )
     {
-        (Ok(id), Ok(user)) => {
+        (Some(id), Ok(user)) => {
             match validate(id, &user).await {
                 Ok(_) => (),
-                Err(e) => return (BAD_REQUEST.to_string(), e.to_string()),
+                Err(e) => return e.into(),
             }
 
             let user = user.tranform_to_user();
-            match get_user_by_id(&user.id, db).await {
+            match get_user_by_id(&user.id, &db).await {
                 Ok(_) => {},
                 Err(e) => {
                     error!("Error getting user with id '{}': {}", user.id, e);
-                    return (NOT_FOUND.to_string(), "User not found".to_string())
+                    return AppError::NotFound("User not found".to_string()).into()
                 }
             }
 
-            match update_user(&user, db).await {
+            match update_user(&user, &db).await {
                 Ok(_) => {},
                 Err(e) => {
                     error!("Error updating user with id '{}': {}", user.id, e);
-                    return (INTERNAL_ERROR.to_string(), "Failed to update user".to_string())
+                    return AppError::Internal("Failed to update user".to_string()).into()
                 }
             }
             
-            let user = match get_user_by_id(&user.id, db).await {
+            let user = match get_user_by_id(&user.id, &db).await {
                 Ok(user) => user,
                 _ => {
                     error!("Error getting user with id '{}'", user.id);
-                    return (INTERNAL_ERROR.to_string(), "Internal error".to_string())
+                    return AppError::Internal("Internal error".to_string()).into()
                 }
             };
 
             let user = user.tranform_to_user_response();
             match serde_json::to_string(&user) {
-                Ok(user) => (OK_RESPONSE.to_string(), user),
+                Ok(user) => Response::json(StatusCode::Ok, user),
                 Err(e) => {
                     error!("Error serializing user: {:?}", e);
-                    (INTERNAL_ERROR.to_string(), "Internal error".to_string())
+                    AppError::Internal("Internal error".to_string()).into()
                 }
             }
         }
-        _ => {
-            error!("Error updating user");
-            (INTERNAL_ERROR.to_string(), "Internal error".to_string())
+        (None, _) => AppError::BadRequest("Invalid user id".to_string()).into(),
+        (_, Err(msg)) => {
+            error!("Error updating user: {}", msg);
+            AppError::BadRequest(msg).into()
         }
     }
 }
 
-async fn validate(id: i32, user: &UserUpdateInput) -> Result<(), Box<dyn std::error::Error>> {
-    if id != user.id {
-        return Err("User id in path does not match user id in body".into())
-    }
-
-    if user.name.is_empty() {
-        return Err("Missing name".into());
-    }
+async fn validate(id: i32, user: &UserUpdateInput) -> Result<(), AppError> {
+    let mut errors = user.validate();
 
-    if user.name.len() < 2 {
-        return Err("Name must be at least 2 characters long".into());
+    if id != user.id {
+        errors.add("id", "User id in path does not match user id in body");
     }
 
-    Ok(())
+    errors.into_result()
 }
\ No newline at end of file
@@ -1,25 +1,34 @@
-use tokio_postgres::Client;
-use crate::libs::{ get_id, INTERNAL_ERROR, OK_RESPONSE, NOT_FOUND };
+use crate::libs::request::Request;
+use crate::AppState;
+use crate::libs::error::AppError;
+use crate::libs::response::{Response, StatusCode};
 use super::super::repository::get_user_by_id;
 use log::error;
 
-pub async fn handle(request: &str, db: &Client) -> (String, String) {
-    match get_id(&request).parse::<i32>() {
-        Ok(id) => {
-            let user = match get_user_by_id(&id, db).await {
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+    match request.param::<i32>("id") {
+        Some(id) => {
+            let user = match get_user_by_id(&id, &db).await {
                 Ok(user) => user,
-                _ => return (NOT_FOUND.to_string(), "User not found".to_string()),
+                _ => return AppError::NotFound("User not found".to_string()).into(),
             };
 
             let user_response = user.tranform_to_user_response(); 
             match serde_json::to_string(&user_response) {
-                Ok(user) => (OK_RESPONSE.to_string(), user),
+                Ok(user) => Response::json(StatusCode::Ok, user),
                 Err(e) => {
                     error!("Error serializing user: {:?}", e);
-                    (INTERNAL_ERROR.to_string(), "Internal error".to_string())
+                    AppError::Internal("Internal error".to_string()).into()
                 }
             } 
         }
-        _ => (INTERNAL_ERROR.to_string(), "Internal error".to_string()),
+        None => AppError::BadRequest("Invalid user id".to_string()).into(),
     }
 }
\ No newline at end of file
@@ -1,17 +1,62 @@
-use tokio_postgres::Client;
-use super::super::repository::list_users;
-use crate::libs::{INTERNAL_ERROR, OK_RESPONSE};
+use log::error;
+use crate::AppState;
+use crate::libs::request::Request;
+use super::super::repository::{count_users, list_users};
+use super::util::get_user_list_query;
+use crate::libs::error::AppError;
+use crate::libs::pagination::{encode_cursor, Page, Pagination};
+use crate::libs::response::{Response, StatusCode};
 use super::super::model::tranform_users_to_user_responses;
 
-pub async fn handle(_request: &str, client: &Client) -> (String, String) {
-    let users = match list_users(client).await {
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let query = match get_user_list_query(request) {
+        Ok(query) => query,
+        Err(e) => return e.into(),
+    };
+
+    let db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    // One extra row tells whether there is a next page
+    let mut users = match list_users(&query, &db).await {
         Ok(users) => users,
-        _ => return (INTERNAL_ERROR.to_string(), "Internal error".to_string()),
+        Err(e) => {
+            error!("Error listing users: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+    let has_more = users.len() as i64 > query.limit;
+    users.truncate(query.limit as usize);
+
+    let total = match count_users(&query, &db).await {
+        Ok(total) => total,
+        Err(e) => {
+            error!("Error counting users: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    let next_cursor = match users.last() {
+        Some(user) if has_more => Some(encode_cursor(&user.cursor(query.sort))),
+        _ => None,
+    };
+    let page = Page {
+        data: tranform_users_to_user_responses(users),
+        pagination: Pagination {
+            limit: query.limit,
+            offset: query.offset,
+            total,
+            next_cursor,
+        },
     };
 
-    let user_responses = tranform_users_to_user_responses(users);
-    match serde_json::to_string(&user_responses) {
-        Ok(user_responses) => (OK_RESPONSE.to_string(), user_responses),  
-        _ => (INTERNAL_ERROR.to_string(), "Internal error".to_string()),
+    match serde_json::to_string(&page) {
+        Ok(page) => Response::json(StatusCode::Ok, page),
+        _ => AppError::Internal("Internal error".to_string()).into(),
     }
-}
+}
\ No newline at end of file
@@ -0,0 +1,116 @@
+use tokio_postgres::Client;
+use tokio_postgres::error::SqlState;
+use crate::libs::request::Request;
+use super::util::get_user_patch_input;
+use crate::AppState;
+use crate::auth::verification::send_verification_email;
+use crate::libs::error::{AppError, ErrorDetail};
+use crate::libs::response::{Response, StatusCode};
+use crate::libs::role::Role;
+use crate::libs::validation::Validate;
+use super::super::repository::{get_user_by_id, is_email_exist, patch_user};
+use super::super::model::{User, UserChanges, UserPatchInput};
+use log::error;
+
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let id = match request.param::<i32>("id") {
+        Some(id) => id,
+        None => return AppError::BadRequest("Invalid user id".to_string()).into(),
+    };
+    let input = match get_user_patch_input(request) {
+        Ok(input) => input,
+        Err(msg) => {
+            error!("Error patching user: {}", msg);
+            return AppError::BadRequest(msg).into();
+        }
+    };
+    let is_admin = request.auth.as_ref().is_some_and(|auth| auth.has_role(Role::Admin));
+    if input.role.is_some() && !is_admin {
+        return AppError::Forbidden("Only admins can change roles".to_string()).into();
+    }
+
+    let db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    let current = match get_user_by_id(&id, &db).await {
+        Ok(user) => user,
+        Err(e) => {
+            error!("Error getting user with id '{}': {}", id, e);
+            return AppError::NotFound("User not found".to_string()).into();
+        }
+    };
+
+    let changes = input.changes(&current);
+    if let Err(e) = validate(&input, &changes, &db).await {
+        return e.into();
+    }
+
+    let user = if changes.is_empty() {
+        current
+    } else {
+        match patch_user(&id, &changes, &db).await {
+            Ok(_) => {},
+            // Another request took the email between the check and the update
+            Err(e) if e.code() == Some(&SqlState::UNIQUE_VIOLATION) => {
+                return AppError::Validation(vec![ErrorDetail {
+                    field: Some("email".to_string()),
+                    message: "Email already exists".to_string(),
+                }]).into();
+            }
+            Err(e) => {
+                error!("Error patching user with id '{}': {}", id, e);
+                return AppError::Internal("Failed to update user".to_string()).into();
+            }
+        }
+
+        let user = match get_user_by_id(&id, &db).await {
+            Ok(user) => user,
+            _ => {
+                error!("Error getting user with id '{}'", id);
+                return AppError::Internal("Internal error".to_string()).into();
+            }
+        };
+        if changes.email.is_some() {
+            send_verification_email(state, user.id, &user.name, &user.email);
+        }
+        user
+    };
+
+    respond(&user)
+}
+
+fn respond(user: &User) -> Response {
+    match serde_json::to_string(&user.tranform_to_user_response()) {
+        Ok(user) => Response::json(StatusCode::Ok, user),
+        Err(e) => {
+            error!("Error serializing user: {:?}", e);
+            AppError::Internal("Internal error".to_string()).into()
+        }
+    }
+}
+
+// Only the fields present in the body are validated, uniqueness only when the email changes
+async fn validate(input: &UserPatchInput, changes: &UserChanges, db: &Client) -> Result<(), AppError> {
+    let mut errors = input.validate();
+
+    if let Some(email) = &changes.email {
+        if !errors.has("email") {
+            match is_email_exist(email, db).await {
+                Ok(is_exist) => if is_exist {
+                    errors.add("email", "Email already exists");
+                }
+                Err(e) => {
+                    error!("Error checking if email already exists: {:?}", e);
+                    return Err(AppError::Internal("Internal error".to_string()));
+                }
+            }
+        }
+    }
+
+    errors.into_result()
+}
\ No newline at end of file
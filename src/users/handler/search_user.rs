@@ -0,0 +1,64 @@
+use log::error;
+use crate::AppState;
+use crate::libs::request::Request;
+use super::super::repository::{count_search_users, search_users};
+use super::util::get_user_search_query;
+use crate::libs::error::AppError;
+use crate::libs::pagination::{encode_cursor, Page, Pagination};
+use crate::libs::response::{Response, StatusCode};
+use super::super::model::{SearchCursor, UserSearchResult};
+
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let query = match get_user_search_query(request) {
+        Ok(query) => query,
+        Err(e) => return e.into(),
+    };
+
+    let db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    let mut results = match search_users(&query, &db).await {
+        Ok(results) => results,
+        Err(e) => {
+            error!("Error searching users: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+    let has_more = results.len() as i64 > query.limit;
+    results.truncate(query.limit as usize);
+
+    let total = match count_search_users(&query, &db).await {
+        Ok(total) => total,
+        Err(e) => {
+            error!("Error counting user search results: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    let next_cursor = match results.last() {
+        Some((user, score)) if has_more => Some(encode_cursor(&SearchCursor { score: *score, id: user.id })),
+        _ => None,
+    };
+    let page = Page {
+        data: results
+            .into_iter()
+            .map(|(user, score)| UserSearchResult { user: user.tranform_to_user_response(), score })
+            .collect(),
+        pagination: Pagination {
+            limit: query.limit,
+            offset: query.offset,
+            total,
+            next_cursor,
+        },
+    };
+
+    match serde_json::to_string(&page) {
+        Ok(page) => Response::json(StatusCode::Ok, page),
+        _ => AppError::Internal("Internal error".to_string()).into(),
+    }
+}
\ No newline at end of file
@@ -0,0 +1,37 @@
+use crate::libs::request::Request;
+use log::{error, info};
+use crate::AppState;
+use crate::auth::throttle;
+use crate::libs::error::AppError;
+use crate::libs::response::{Response, StatusCode};
+use super::super::repository::get_user_by_id;
+
+// Clears the failed logins of the user's email, which lifts a lockout or backoff at once
+pub async fn handle(request: &Request, state: &AppState) -> Response {
+    let id = match request.param::<i32>("id") {
+        Some(id) => id,
+        None => return AppError::BadRequest("Invalid user id".to_string()).into(),
+    };
+
+    let db = match state.db_pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to get a database connection from the pool: {:?}", e);
+            return AppError::Internal("Internal error".to_string()).into();
+        }
+    };
+
+    let user = match get_user_by_id(&id, &db).await {
+        Ok(user) => user,
+        Err(e) => {
+            error!("Error getting user with id '{}': {}", id, e);
+            return AppError::NotFound("User not found".to_string()).into();
+        }
+    };
+    if let Err(e) = throttle::clear(&user.email, &**db).await {
+        return e.into();
+    }
+
+    info!("Unlocked login of user {}", id);
+    Response::new(StatusCode::NoContent)
+}
\ No newline at end of file
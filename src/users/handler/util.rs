@@ -1,15 +1,108 @@
-use super::super::model::{UserUpdateInput, UserCreateInput};
+use crate::libs::error::AppError;
+use crate::libs::pagination::{decode_cursor, PageRequest};
+use crate::libs::request::Request;
+use crate::libs::validation::ValidationErrors;
+use super::super::model::{SearchCursor, SortDirection, UserCreateInput, UserCursor, UserListQuery, UserPatchInput, UserSearchQuery, UserSort, UserUpdateInput};
 
-pub fn get_user_create_input(request: &str) -> Result<UserCreateInput, String> {
-    let body = request.split("\r\n\r\n").last().unwrap_or_default();
-    serde_json::from_str(body).map_err(|e| {
+const MAX_SEARCH_LENGTH: usize = 100;
+
+pub fn get_user_create_input(request: &Request) -> Result<UserCreateInput, String> {
+    serde_json::from_slice(&request.body).map_err(|e| {
+        format!("Failed to parse request body: {}", e)
+    })
+}
+
+pub fn get_user_update_input(request: &Request) -> Result<UserUpdateInput, String> {
+    serde_json::from_slice(&request.body).map_err(|e| {
         format!("Failed to parse request body: {}", e)
     })
 }
 
-pub fn get_user_update_input(request: &str) -> Result<UserUpdateInput, String> {
-    let body = request.split("\r\n\r\n").last().unwrap_or_default();
-    serde_json::from_str(body).map_err(|e| {
+pub fn get_user_patch_input(request: &Request) -> Result<UserPatchInput, String> {
+    serde_json::from_slice(&request.body).map_err(|e| {
         format!("Failed to parse request body: {}", e)
     })
+}
+
+// Query string of GET /users: limit, offset, cursor, sort, direction, name_contains, email_domain
+pub fn get_user_list_query(request: &Request) -> Result<UserListQuery, AppError> {
+    let mut errors = ValidationErrors::new();
+    let page = PageRequest::from_request(request, &mut errors);
+
+    let sort = match request.query.get("sort").map(|s| s.as_str()) {
+        None | Some("id") => UserSort::Id,
+        Some("name") => UserSort::Name,
+        Some("email") => UserSort::Email,
+        Some(_) => {
+            errors.add("sort", "sort must be one of id, name, email");
+            UserSort::Id
+        }
+    };
+
+    let direction = match request.query.get("direction").map(|s| s.to_ascii_lowercase()).as_deref() {
+        None | Some("asc") => SortDirection::Asc,
+        Some("desc") => SortDirection::Desc,
+        Some(_) => {
+            errors.add("direction", "direction must be asc or desc");
+            SortDirection::Asc
+        }
+    };
+
+    let cursor = match &page.cursor {
+        Some(cursor) => match decode_cursor::<UserCursor>(cursor) {
+            Some(cursor) => Some(cursor),
+            None => {
+                errors.add("cursor", "Invalid cursor");
+                None
+            }
+        },
+        None => None,
+    };
+
+    let non_empty = |name: &str| request.query.get(name).map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    let name_contains = non_empty("name_contains");
+    let email_domain = non_empty("email_domain").map(|d| d.trim_start_matches('@').to_string());
+
+    errors.into_result()?;
+    Ok(UserListQuery {
+        limit: page.limit,
+        offset: page.offset,
+        cursor,
+        sort,
+        direction,
+        name_contains,
+        email_domain,
+    })
+}
+
+// Query string of GET /users/search: q plus the paging parameters of GET /users
+pub fn get_user_search_query(request: &Request) -> Result<UserSearchQuery, AppError> {
+    let mut errors = ValidationErrors::new();
+    let page = PageRequest::from_request(request, &mut errors);
+
+    let q = request.query.get("q").map(|q| q.trim().to_string()).unwrap_or_default();
+    if q.is_empty() {
+        errors.add("q", "Missing search query");
+    } else if q.chars().count() > MAX_SEARCH_LENGTH {
+        errors.add("q", &format!("Search query must be at most {} characters long", MAX_SEARCH_LENGTH));
+    }
+
+    let cursor = match &page.cursor {
+        Some(cursor) => match decode_cursor::<SearchCursor>(cursor) {
+            Some(cursor) => Some(cursor),
+            None => {
+                errors.add("cursor", "Invalid cursor");
+                None
+            }
+        },
+        None => None,
+    };
+
+    errors.into_result()?;
+    Ok(UserSearchQuery {
+        q,
+        limit: page.limit,
+        offset: page.offset,
+        cursor,
+    })
 }
\ No newline at end of file
@@ -1,9 +1,16 @@
+use std::sync::OnceLock;
+use regex::Regex;
+use crate::libs::role::Role;
+use crate::libs::validation::{Validate, ValidationErrors};
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct User {
     pub id: i32,
     pub name: String,
     pub email: String,
     pub password: String,
+    pub role: Role,
+    pub email_verified: bool,
 }
 
 impl User {
@@ -12,26 +19,77 @@ impl User {
             id: self.id,
             name: self.name.clone(),
             email: self.email.clone(),
+            role: self.role,
+            email_verified: self.email_verified,
         }
     }
 }
 
+// What login needs to know about the user of an email
+#[derive(Debug)]
+pub struct Credentials {
+    pub id: i32,
+    pub password: String,
+    pub role: Role,
+    pub email_verified: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct UserCreateInput {
     pub name: String,
     pub email: String,
     pub password: String,
     pub confirm_password: String,
+    // Defaults to user
+    #[serde(default)]
+    pub role: Option<Role>,
 }
 
 impl UserCreateInput {
-    pub fn tranform_to_user(&self, hash_password: String) -> User {
+    pub fn tranform_to_user(&self, hash_password: String, email_verified: bool) -> User {
         User {
             id: 0,
             name: self.name.clone(),
             email: self.email.clone(),
             password: hash_password,
+            role: self.role.unwrap_or(Role::User),
+            email_verified,
+        }
+    }
+}
+
+impl Validate for UserCreateInput {
+    fn validate(&self) -> ValidationErrors {
+        let mut errors = ValidationErrors::new();
+        validate_name(&self.name, &mut errors);
+
+        if self.email.is_empty() {
+            errors.add("email", "Missing email");
+        } else if !email_regex().is_match(&self.email) {
+            errors.add("email", "Invalid email format");
+        }
+
+        validate_new_password(&self.password, &self.confirm_password, &mut errors);
+        errors
+    }
+}
+
+// Body of PUT /users/{id}/password
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PasswordChangeInput {
+    pub current_password: String,
+    pub password: String,
+    pub confirm_password: String,
+}
+
+impl Validate for PasswordChangeInput {
+    fn validate(&self) -> ValidationErrors {
+        let mut errors = ValidationErrors::new();
+        if self.current_password.is_empty() {
+            errors.add("current_password", "Missing current password");
         }
+        validate_new_password(&self.password, &self.confirm_password, &mut errors);
+        errors
     }
 }
 
@@ -48,17 +106,200 @@ impl UserUpdateInput {
             name: self.name.clone(),
             email: "".to_string(),
             password: "".to_string(),
+            role: Role::User,
+            email_verified: false,
         }
     }
 }
 
+// Sparse body of PATCH /users/{id} following JSON Merge Patch (RFC 7396). A field that is
+// absent stays unchanged, Some(None) means the client sent null.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct UserPatchInput {
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub email: Option<Option<String>>,
+    // Only admins may change roles
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub role: Option<Option<Role>>,
+}
+
+fn deserialize_present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::Deserialize<'de>,
+{
+    <Option<T> as serde::Deserialize>::deserialize(deserializer).map(Some)
+}
+
+impl UserPatchInput {
+    // Columns whose requested value differs from the stored user
+    pub fn changes(&self, current: &User) -> UserChanges {
+        UserChanges {
+            name: self.name.clone().flatten().filter(|name| *name != current.name),
+            email: self.email.clone().flatten().filter(|email| *email != current.email),
+            role: self.role.flatten().filter(|role| *role != current.role),
+        }
+    }
+}
+
+impl Validate for UserPatchInput {
+    fn validate(&self) -> ValidationErrors {
+        let mut errors = ValidationErrors::new();
+        match &self.name {
+            Some(Some(name)) => validate_name(name, &mut errors),
+            Some(None) => errors.add("name", "name cannot be null"),
+            None => {}
+        }
+        match &self.email {
+            Some(Some(email)) => {
+                if email.is_empty() {
+                    errors.add("email", "Missing email");
+                } else if !email_regex().is_match(email) {
+                    errors.add("email", "Invalid email format");
+                }
+            }
+            Some(None) => errors.add("email", "email cannot be null"),
+            None => {}
+        }
+        if let Some(None) = self.role {
+            errors.add("role", "role cannot be null");
+        }
+        errors
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct UserChanges {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub role: Option<Role>,
+}
+
+impl UserChanges {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.email.is_none() && self.role.is_none()
+    }
+}
+
+impl Validate for UserUpdateInput {
+    fn validate(&self) -> ValidationErrors {
+        let mut errors = ValidationErrors::new();
+        validate_name(&self.name, &mut errors);
+        errors
+    }
+}
+
+fn validate_name(name: &str, errors: &mut ValidationErrors) {
+    if name.is_empty() {
+        errors.add("name", "Missing name");
+    } else if name.len() < 2 {
+        errors.add("name", "Name must be at least 2 characters long");
+    }
+}
+
+// Password strength is checked by the configured PasswordPolicy
+pub fn validate_new_password(password: &str, confirm_password: &str, errors: &mut ValidationErrors) {
+    if password.is_empty() {
+        errors.add("password", "Missing password");
+    }
+
+    if password != confirm_password {
+        errors.add("confirm_password", "Passwords do not match");
+    }
+}
+
+// No whitespace or control characters, the address ends up in SMTP commands and headers
+fn email_regex() -> &'static Regex {
+    static EMAIL_REGEX: OnceLock<Regex> = OnceLock::new();
+    EMAIL_REGEX.get_or_init(|| Regex::new(r"^[^@\s]+@[^@\s]+\.[^@\s]+$").expect("Invalid email regex"))
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct UserResponse {
     pub id: i32,
     pub name: String,
     pub email: String,
+    pub role: Role,
+    pub email_verified: bool,
 }
 
 pub fn tranform_users_to_user_responses(users: Vec<User>) -> Vec<UserResponse> {
     users.into_iter().map(|user| user.tranform_to_user_response()).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserSort {
+    Id,
+    Name,
+    Email,
+}
+
+impl UserSort {
+    pub fn column(&self) -> &'static str {
+        match self {
+            UserSort::Id => "id",
+            UserSort::Name => "name",
+            UserSort::Email => "email",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+// Position of the last returned row, the id breaks ties between equal sort values
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UserCursor {
+    pub value: String,
+    pub id: i32,
+}
+
+#[derive(Debug)]
+pub struct UserListQuery {
+    pub limit: i64,
+    pub offset: i64,
+    pub cursor: Option<UserCursor>,
+    pub sort: UserSort,
+    pub direction: SortDirection,
+    pub name_contains: Option<String>,
+    pub email_domain: Option<String>,
+}
+
+impl User {
+    pub fn cursor(&self, sort: UserSort) -> UserCursor {
+        let value = match sort {
+            UserSort::Id => self.id.to_string(),
+            UserSort::Name => self.name.clone(),
+            UserSort::Email => self.email.clone(),
+        };
+        UserCursor { value, id: self.id }
+    }
+}
+
+// Position of the last returned search result, ordered by score then id
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SearchCursor {
+    pub score: f32,
+    pub id: i32,
+}
+
+#[derive(Debug)]
+pub struct UserSearchQuery {
+    pub q: String,
+    pub limit: i64,
+    pub offset: i64,
+    pub cursor: Option<SearchCursor>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct UserSearchResult {
+    #[serde(flatten)]
+    pub user: UserResponse,
+    pub score: f32,
 }
\ No newline at end of file
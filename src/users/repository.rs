@@ -1,11 +1,13 @@
 use log::info;
-use tokio_postgres::{Client, Error, Transaction};
-use super::model::User;
+use tokio_postgres::{Client, Error, GenericClient, Transaction};
+use tokio_postgres::types::ToSql;
+use super::model::{Credentials, SortDirection, User, UserChanges, UserListQuery, UserSearchQuery, UserSort};
 
 pub async fn insert_user<'a>(user: &User, tx: &'a Transaction<'a>) -> Result<User, Error>{  
     let row = tx.query_one(
-        "INSERT INTO users (name, email, password) VALUES ($1, $2, $3) RETURNING id",
-        &[&user.name, &user.email, &user.password],
+        "INSERT INTO users (name, email, password, role, email_verified_at) \
+         VALUES ($1, $2, $3, $4, CASE WHEN $5 THEN now() END) RETURNING id",
+        &[&user.name, &user.email, &user.password, &user.role.as_str(), &user.email_verified],
     ).await?;
     
     let id: i32 = row.get(0);
@@ -15,24 +17,69 @@ pub async fn insert_user<'a>(user: &User, tx: &'a Transaction<'a>) -> Result<Use
         name: user.name.clone(),
         email: user.email.clone(),
         password: "".to_string(),
+        role: user.role,
+        email_verified: user.email_verified,
     })
 }
 
 pub async fn get_user_by_id(id: &i32, db: &Client) -> Result<User, Error>{  
-    let row = db.query_one("SELECT id, name, email FROM users WHERE id = $1", &[id]).await?;
+    let row = db.query_one("SELECT id, name, email, role, email_verified_at IS NOT NULL FROM users WHERE id = $1", &[id]).await?;
     Ok(User {
         id: row.get(0),
         name: row.get(1),
         email: row.get(2),
         password: "".to_string(),
+        role: row.get(3),
+        email_verified: row.get(4),
     })
 }
 
-pub async fn get_password_by_email(email: &str, db: &Client) -> Result<String, Error>{  
-    let row = db.query_one("SELECT password FROM users WHERE email = $1", &[&email]).await?;
+pub async fn get_password_by_id(id: &i32, db: &Client) -> Result<String, Error> {
+    let row = db.query_one("SELECT password FROM users WHERE id = $1", &[id]).await?;
     Ok(row.get(0))
 }
 
+// None when no user has this email
+pub async fn get_credentials_by_email(email: &str, db: &Client) -> Result<Option<Credentials>, Error>{  
+    let row = db.query_opt(
+        "SELECT id, password, role, email_verified_at IS NOT NULL FROM users WHERE email = $1",
+        &[&email],
+    ).await?;
+    Ok(row.map(|row| Credentials {
+        id: row.get(0),
+        password: row.get(1),
+        role: row.get(2),
+        email_verified: row.get(3),
+    }))
+}
+
+pub async fn get_user_by_email(email: &str, db: &Client) -> Result<Option<User>, Error>{  
+    let row = db.query_opt(
+        "SELECT id, name, email, role, email_verified_at IS NOT NULL FROM users WHERE email = $1",
+        &[&email],
+    ).await?;
+    Ok(row.map(|row| User {
+        id: row.get(0),
+        name: row.get(1),
+        email: row.get(2),
+        password: "".to_string(),
+        role: row.get(3),
+        email_verified: row.get(4),
+    }))
+}
+
+// Only marks the address the link was sent to, returns 0 when it changed or was already verified
+pub async fn mark_email_verified(id: &i32, email: &str, db: &Client) -> Result<u64, Error> {
+    db.execute(
+        "UPDATE users SET email_verified_at = now() WHERE id = $1 AND email = $2 AND email_verified_at IS NULL",
+        &[id, &email],
+    ).await
+}
+
+pub async fn update_password(id: &i32, hash_password: &str, db: &impl GenericClient) -> Result<u64, Error> {
+    db.execute("UPDATE users SET password = $1 WHERE id = $2", &[&hash_password, id]).await
+}
+
 pub async fn is_email_exist(email: &str, db: &Client) -> Result<bool, Error>{  
     let row = db.query_opt("SELECT 1 FROM users WHERE email = $1", &[&email]).await?;
     Ok(row.is_some())
@@ -48,8 +95,67 @@ pub async fn update_user(user: &User, db: &Client) -> Result<u64, Error> {
     Ok(rows_affected)
 }
 
-pub async fn list_users(db: &Client) -> Result<Vec<User>, Error> {
-    let rows = db.query("SELECT id, name, email FROM users", &[]).await?;
+// Updates only the columns present in changes
+pub async fn patch_user(id: &i32, changes: &UserChanges, db: &Client) -> Result<u64, Error> {
+    let mut columns = Vec::new();
+    let mut params: Vec<SqlParam> = Vec::new();
+    if let Some(name) = &changes.name {
+        params.push(Box::new(name.clone()));
+        columns.push(format!("name = ${}", params.len()));
+    }
+    if let Some(email) = &changes.email {
+        params.push(Box::new(email.clone()));
+        columns.push(format!("email = ${}", params.len()));
+        // A new address has to be verified again
+        columns.push("email_verified_at = NULL".to_string());
+    }
+    if let Some(role) = &changes.role {
+        params.push(Box::new(role.as_str()));
+        columns.push(format!("role = ${}", params.len()));
+    }
+    if columns.is_empty() {
+        return Ok(0);
+    }
+    params.push(Box::new(*id));
+    let sql = format!("UPDATE users SET {} WHERE id = ${}", columns.join(", "), params.len());
+    let rows_affected = db.execute(&sql, &as_params(&params)).await?;
+    Ok(rows_affected)
+}
+
+// Returns up to limit + 1 rows so the caller can tell whether another page follows
+pub async fn list_users(query: &UserListQuery, db: &Client) -> Result<Vec<User>, Error> {
+    let (mut conditions, mut params) = user_filters(query);
+
+    let column = query.sort.column();
+    let (comparison, order) = match query.direction {
+        SortDirection::Asc => (">", "ASC"),
+        SortDirection::Desc => ("<", "DESC"),
+    };
+    if let Some(cursor) = &query.cursor {
+        if query.sort == UserSort::Id {
+            params.push(Box::new(cursor.id));
+            conditions.push(format!("id {} ${}", comparison, params.len()));
+        } else {
+            params.push(Box::new(cursor.value.clone()));
+            params.push(Box::new(cursor.id));
+            conditions.push(format!("({}, id) {} (${}, ${})", column, comparison, params.len() - 1, params.len()));
+        }
+    }
+
+    let mut sql = "SELECT id, name, email, role, email_verified_at IS NOT NULL FROM users".to_string();
+    if !conditions.is_empty() {
+        sql.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+    }
+    if query.sort == UserSort::Id {
+        sql.push_str(&format!(" ORDER BY id {}", order));
+    } else {
+        sql.push_str(&format!(" ORDER BY {} {}, id {}", column, order, order));
+    }
+    params.push(Box::new(query.limit + 1));
+    params.push(Box::new(query.offset));
+    sql.push_str(&format!(" LIMIT ${} OFFSET ${}", params.len() - 1, params.len()));
+
+    let rows = db.query(&sql, &as_params(&params)).await?;
     let mut users = Vec::new();
     for row in rows {
         users.push(User {
@@ -57,8 +163,94 @@ pub async fn list_users(db: &Client) -> Result<Vec<User>, Error> {
             name: row.get(1),
             email: row.get(2),
             password: "".to_string(),
+            role: row.get(3),
+            email_verified: row.get(4),
         });
     }
     Ok(users)
 }
-    
\ No newline at end of file
+
+// Number of users matching the filters of the query, ignoring its cursor and paging
+pub async fn count_users(query: &UserListQuery, db: &Client) -> Result<i64, Error> {
+    let (conditions, params) = user_filters(query);
+    let mut sql = "SELECT COUNT(*) FROM users".to_string();
+    if !conditions.is_empty() {
+        sql.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+    }
+    let row = db.query_one(&sql, &as_params(&params)).await?;
+    Ok(row.get(0))
+}
+
+// Ranks users by trigram similarity of name or email to the query. Returns up to
+// limit + 1 rows with their score so the caller can tell whether another page follows.
+pub async fn search_users(query: &UserSearchQuery, db: &Client) -> Result<Vec<(User, f32)>, Error> {
+    let mut params: Vec<SqlParam> = vec![
+        Box::new(query.q.clone()),
+        Box::new(format!("%{}%", escape_like(&query.q))),
+    ];
+    let mut sql = format!("SELECT id, name, email, role, email_verified, score FROM ({}) AS matches", SEARCH_SQL);
+    if let Some(cursor) = &query.cursor {
+        params.push(Box::new(cursor.score));
+        params.push(Box::new(cursor.id));
+        sql.push_str(" WHERE score < $3 OR (score = $3 AND id > $4)");
+    }
+    params.push(Box::new(query.limit + 1));
+    params.push(Box::new(query.offset));
+    sql.push_str(&format!(" ORDER BY score DESC, id ASC LIMIT ${} OFFSET ${}", params.len() - 1, params.len()));
+
+    let rows = db.query(&sql, &as_params(&params)).await?;
+    let mut users = Vec::new();
+    for row in rows {
+        users.push((
+            User {
+                id: row.get(0),
+                name: row.get(1),
+                email: row.get(2),
+                password: "".to_string(),
+                role: row.get(3),
+                email_verified: row.get(4),
+            },
+            row.get(5),
+        ));
+    }
+    Ok(users)
+}
+
+pub async fn count_search_users(query: &UserSearchQuery, db: &Client) -> Result<i64, Error> {
+    let pattern = format!("%{}%", escape_like(&query.q));
+    let sql = format!("SELECT COUNT(*) FROM ({}) AS matches", SEARCH_SQL);
+    let row = db.query_one(&sql, &[&query.q, &pattern]).await?;
+    Ok(row.get(0))
+}
+
+// $1 is the search text and $2 the escaped ILIKE pattern. Needs the pg_trgm extension,
+// see migrations/002_users_search.sql.
+const SEARCH_SQL: &str = "SELECT id, name, email, role, email_verified_at IS NOT NULL AS email_verified, \
+    GREATEST(similarity(name, $1), similarity(email, $1), word_similarity($1, name), word_similarity($1, email)) AS score \
+    FROM users \
+    WHERE name ILIKE $2 OR email ILIKE $2 OR name % $1 OR email % $1";
+
+type SqlParam = Box<dyn ToSql + Sync + Send>;
+
+fn user_filters(query: &UserListQuery) -> (Vec<String>, Vec<SqlParam>) {
+    let mut conditions = Vec::new();
+    let mut params: Vec<SqlParam> = Vec::new();
+    if let Some(name) = &query.name_contains {
+        params.push(Box::new(format!("%{}%", escape_like(name))));
+        conditions.push(format!("name ILIKE ${}", params.len()));
+    }
+    if let Some(domain) = &query.email_domain {
+        params.push(Box::new(format!("%@{}", escape_like(domain))));
+        conditions.push(format!("email ILIKE ${}", params.len()));
+    }
+    (conditions, params)
+}
+
+fn as_params(params: &[SqlParam]) -> Vec<&(dyn ToSql + Sync)> {
+    params.iter().map(|p| p.as_ref() as &(dyn ToSql + Sync)).collect()
+}
+
+// Escape LIKE wildcards so user input is matched literally
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}